@@ -0,0 +1,80 @@
+// Unread count in the title while the tab is in the background, with an opt-in beep and notifications.
+const impermachatAlerts = (() => {
+    const baseTitle = document.title;
+    let unread = 0;
+
+    // the server sets this cookie from the Alerts panel
+    function prefs() {
+        const cookie = document.cookie.split('; ').find(c => c.startsWith('impermachat_alerts='));
+        const values = cookie ? cookie.split('=')[1].split(',') : [];
+        return { sound: values.includes('sound'), notify: values.includes('notify') };
+    }
+
+    function showCount() {
+        document.title = unread > 0 ? '(' + unread + ') ' + baseTitle : baseTitle;
+    }
+
+    document.addEventListener('visibilitychange', () => {
+        if (!document.hidden) {
+            unread = 0;
+            showCount();
+        }
+    });
+
+    function canNotify() {
+        return 'Notification' in window && Notification.permission === 'granted';
+    }
+
+    // who wrote a chat log entry and what it says, for notification text
+    function describe(message) {
+        if (!message.classList.contains('box')) {
+            return { name: '', text: message.textContent.trim() };
+        }
+        const name = message.querySelector('.titlebar')?.firstChild?.textContent.trim() || 'Someone';
+        const text = Array.from(message.childNodes)
+            .filter(node => !node.classList?.contains('titlebar'))
+            .map(node => node.textContent)
+            .join('')
+            .trim();
+        return { name, text };
+    }
+
+    function notify(title, message) {
+        const notification = new Notification(title, { body: describe(message).text, tag: 'impermachat' });
+        notification.onclick = () => {
+            window.focus();
+            message.scrollIntoView({behavior: 'smooth', block: 'center'});
+        };
+    }
+
+    function arrived(id, count) {
+        if (!document.hidden) {
+            return;
+        }
+        unread += count;
+        showCount();
+
+        const message = document.getElementById('message-' + id);
+        // mentions already got their own alert
+        if (!message || message.classList.contains('mentioned')) {
+            return;
+        }
+        const { sound, notify: wantsNotifications } = prefs();
+        if (wantsNotifications && canNotify()) {
+            const name = describe(message).name;
+            notify(name ? name + ' in ' + baseTitle : baseTitle, message);
+        }
+        if (sound) {
+            impermachatExpiry.beep();
+        }
+    }
+
+    // browsers only ask from a click, so this runs as the box is ticked
+    function askPermission(checkbox) {
+        if (checkbox.checked && 'Notification' in window && Notification.permission === 'default') {
+            Notification.requestPermission();
+        }
+    }
+
+    return { arrived, askPermission, canNotify, describe, notify, prefs };
+})();
@@ -0,0 +1,123 @@
+// End-to-end encryption for rooms created with it enabled. The AES-GCM key lives in the
+// URL fragment (#key=...), which browsers never send to the server, so the server only
+// ever stores and relays the base64 ciphertext produced here.
+const impermachatE2E = (() => {
+    const encoder = new TextEncoder();
+    const decoder = new TextDecoder();
+    let keyPromise = null;
+    let typingQueue = Promise.resolve();
+
+    function toBase64(bytes) {
+        let binary = '';
+        bytes.forEach((byte) => { binary += String.fromCharCode(byte); });
+        return btoa(binary);
+    }
+
+    function fromBase64(text) {
+        return Uint8Array.from(atob(text), (c) => c.charCodeAt(0));
+    }
+
+    function toBase64Url(bytes) {
+        return toBase64(bytes).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+    }
+
+    function fromBase64Url(text) {
+        const padded = text.replace(/-/g, '+').replace(/_/g, '/');
+        return fromBase64(padded + '='.repeat((4 - padded.length % 4) % 4));
+    }
+
+    function keyFromFragment() {
+        const match = window.location.hash.match(/key=([A-Za-z0-9_-]+)/);
+        return match ? match[1] : null;
+    }
+
+    // the room creator mints the key on their first visit, everyone else has to bring it.
+    // Once there's history the server stops offering, so a lost link shows the banner
+    // instead of a new key that can't read anything already said
+    async function init(canCreateKey) {
+        let rawKey = keyFromFragment();
+        if (!rawKey && canCreateKey) {
+            const bytes = crypto.getRandomValues(new Uint8Array(32));
+            rawKey = toBase64Url(bytes);
+            history.replaceState(null, '', window.location.pathname + window.location.search + '#key=' + rawKey);
+        }
+        if (!rawKey) {
+            document.getElementById('e2e-missing-key').style.display = 'block';
+            return;
+        }
+        keyPromise = crypto.subtle.importKey('raw', fromBase64Url(rawKey), 'AES-GCM', false, ['encrypt', 'decrypt']);
+
+        const observer = new MutationObserver(() => decryptAll());
+        observer.observe(document.body, { childList: true, subtree: true, attributes: true, attributeFilter: ['data-cipher'] });
+        decryptAll();
+    }
+
+    async function encrypt(text) {
+        if (!keyPromise || text === '') {
+            return '';
+        }
+        const key = await keyPromise;
+        const iv = crypto.getRandomValues(new Uint8Array(12));
+        const ciphertext = new Uint8Array(await crypto.subtle.encrypt({ name: 'AES-GCM', iv }, key, encoder.encode(text)));
+        const combined = new Uint8Array(iv.length + ciphertext.length);
+        combined.set(iv);
+        combined.set(ciphertext, iv.length);
+        return toBase64(combined);
+    }
+
+    async function decrypt(payload) {
+        const key = await keyPromise;
+        const bytes = fromBase64(payload);
+        const plaintext = await crypto.subtle.decrypt({ name: 'AES-GCM', iv: bytes.slice(0, 12) }, key, bytes.slice(12));
+        return decoder.decode(plaintext);
+    }
+
+    function decryptAll() {
+        if (!keyPromise) {
+            return;
+        }
+        document.querySelectorAll('.e2e-cipher:not([data-decrypted])').forEach(async (el) => {
+            el.setAttribute('data-decrypted', '');
+            const payload = el.getAttribute('data-cipher');
+            if (!payload) {
+                return;
+            }
+            try {
+                el.textContent = await decrypt(payload);
+            } catch (e) {
+                el.textContent = '[unable to decrypt - wrong room key?]';
+                el.classList.add('e2e-error');
+            }
+        });
+    }
+
+    function post(url, message) {
+        return fetch(url, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ message }),
+        });
+    }
+
+    // keep typing updates in order even though encryption is async
+    function typing(url) {
+        const input = document.getElementById('room-input');
+        if (impermachatTyping.hidden()) {
+            typingQueue = typingQueue.then(() => post(url, impermachatTyping.placeholder(input.value))).catch(() => {});
+            return;
+        }
+        typingQueue = typingQueue.then(async () => post(url, await encrypt(input.value))).catch(() => {});
+    }
+
+    function submit(url) {
+        const input = document.getElementById('room-input');
+        const text = input.value;
+        input.value = '';
+        if (text.trim() === '') {
+            return;
+        }
+        typingQueue = typingQueue.then(async () => post(url, await encrypt(text))).catch(() => {});
+    }
+
+    return { init, typing, submit };
+})();
@@ -0,0 +1,27 @@
+// Expiry warning banners, which beep when the Alerts panel's sound is on.
+const impermachatExpiry = (() => {
+    function beep() {
+        const AudioContext = window.AudioContext || window.webkitAudioContext;
+        if (!AudioContext) {
+            return;
+        }
+        const context = new AudioContext();
+        const oscillator = context.createOscillator();
+        const gain = context.createGain();
+        oscillator.frequency.value = 880;
+        gain.gain.setValueAtTime(0.2, context.currentTime);
+        gain.gain.exponentialRampToValueAtTime(0.001, context.currentTime + 0.4);
+        oscillator.connect(gain).connect(context.destination);
+        oscillator.start();
+        oscillator.stop(context.currentTime + 0.4);
+        oscillator.onended = () => context.close();
+    }
+
+    function warn() {
+        if (impermachatAlerts.prefs().sound) {
+            beep();
+        }
+    }
+
+    return { warn, beep };
+})();
@@ -0,0 +1,22 @@
+// Someone @mentioned you: flash the message, and if the tab is in the background, say so.
+const impermachatMentions = (() => {
+    function mentioned(id) {
+        const message = document.getElementById('message-' + id);
+        if (!message) {
+            return;
+        }
+        message.classList.add('mention-flash');
+        setTimeout(() => message.classList.remove('mention-flash'), 2000);
+        if (!document.hidden) {
+            return;
+        }
+        // notifications are opt-in from the Alerts panel, even once the browser allows them
+        if (impermachatAlerts.prefs().notify && impermachatAlerts.canNotify()) {
+            impermachatAlerts.notify((impermachatAlerts.describe(message).name || 'Someone') + ' mentioned you', message);
+        } else if (impermachatAlerts.prefs().sound) {
+            impermachatExpiry.beep();
+        }
+    }
+
+    return { mentioned };
+})();
@@ -0,0 +1,81 @@
+// Sends typing as small insert/delete edits instead of the whole textarea on every keystroke.
+// Each edit carries a sequence number; when the server answers 409 it has lost track of this
+// buffer (a submit cleared it, a request went missing) and the whole buffer is sent once to resync.
+const impermachatTyping = (() => {
+    let lastValue = [];
+    let seq = 0;
+    let synced = false;
+    let queue = Promise.resolve();
+
+    // one edit covering everything between the common prefix and suffix
+    function diff(before, after) {
+        let start = 0;
+        while (start < before.length && start < after.length && before[start] === after[start]) {
+            start++;
+        }
+        let beforeEnd = before.length;
+        let afterEnd = after.length;
+        while (beforeEnd > start && afterEnd > start && before[beforeEnd - 1] === after[afterEnd - 1]) {
+            beforeEnd--;
+            afterEnd--;
+        }
+        return { pos: start, delete: beforeEnd - start, insert: after.slice(start, afterEnd).join('') };
+    }
+
+    function post(url, body) {
+        return fetch(url, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(body),
+        });
+    }
+
+    async function sendFull(url, value) {
+        seq = 0;
+        await post(url, { message: value.join(''), seq });
+        lastValue = value;
+        synced = true;
+    }
+
+    async function send(url, value) {
+        if (!synced) {
+            return sendFull(url, value);
+        }
+        const op = diff(lastValue, value);
+        if (op.delete === 0 && op.insert === '') {
+            return;
+        }
+        seq += 1;
+        const response = await post(url, { seq, ops: [op] });
+        if (response.status === 409) {
+            return sendFull(url, value);
+        }
+        lastValue = value;
+    }
+
+    // either the room hides everyone's drafts or this person ticked the box
+    function hidden() {
+        const toggle = document.querySelector('.hide-typing-toggle input');
+        return document.getElementById('room-input').hasAttribute('data-hidden-drafts') || (toggle !== null && toggle.checked);
+    }
+
+    // a hidden draft only tells the server whether something is being typed
+    function placeholder(text) {
+        return text === '' ? '' : '\u2026';
+    }
+
+    function input(url) {
+        const text = document.getElementById('room-input').value;
+        if (hidden()) {
+            // the server drops the buffer while hidden, resync once drafts show again
+            synced = false;
+            queue = queue.then(() => post(url, { message: placeholder(text), seq: 0 })).catch(() => {});
+            return;
+        }
+        // code points, so positions line up with the server's chars
+        const value = Array.from(text);
+        queue = queue.then(() => send(url, value)).catch(() => { synced = false; });
+    }
+
+    return { input, hidden, placeholder };
+})();
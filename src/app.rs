@@ -1,3 +1,4 @@
+use std::net::SocketAddr;
 use tower_http::services::ServeDir;
 use tokio::{
     signal,
@@ -5,14 +6,22 @@ use tokio::{
 };
 use listenfd::ListenFd;
 
+use crate::config::Config;
 use crate::public;
-use crate::rooms;
+use crate::rooms::{
+    self,
+    handlers::AllRooms,
+};
 
-pub struct App {}
+pub struct App {
+    config: Config,
+}
 
 impl App {
     pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self{})
+        Ok(Self{
+            config: Config::from_env(),
+        })
     }
 
     pub async fn serve(self) -> Result<(), Box<dyn std::error::Error>> {
@@ -21,8 +30,13 @@ impl App {
         //     .allow_headers(Any);
         //     .allow_headers([http::header::CONTENT_TYPE]);
 
-        let app = public::routes::public_router()
-            .merge(rooms::routes::rooms_router())
+        let rooms = AllRooms::new(self.config.clone());
+        if let Some(address) = self.config.irc_listen.clone() {
+            tokio::spawn(rooms::irc::serve(rooms.clone(), address));
+        }
+
+        let app = public::routes::public_router(rooms.clone())
+            .merge(rooms::routes::rooms_router(rooms))
             .nest_service("/assets", ServeDir::new("assets"));
 
         let mut listenfd = ListenFd::from_env();
@@ -38,7 +52,8 @@ impl App {
         println!("listening on {}", listener.local_addr().unwrap());
 
         // ensure we have a shutdown signal to abort the deletion task
-        axum::serve(listener, app.into_make_service())
+        // the JSON API rate limits by address
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
             .with_graceful_shutdown(shutdown_signal())
             .await?;
 
@@ -0,0 +1,309 @@
+use futures_util::StreamExt;
+use reqwest::{
+    header::{COOKIE, SET_COOKIE},
+    Client,
+    StatusCode,
+    Url,
+};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::html;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("expected a room link like https://host/room/name, got {0}")]
+    BadUrl(String),
+    #[error("that room doesn't exist (it may have expired)")]
+    RoomNotFound,
+    #[error("that room is invite only, use the full invite link")]
+    InviteRequired,
+    #[error("the server didn't hand out a connection cookie")]
+    NoCookie,
+    #[error("unexpected response: {0}")]
+    Status(StatusCode),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// What the room stream and form responses tell the UI to do.
+#[derive(Debug)]
+pub enum Update {
+    Fragment(String),
+    Signals(String),
+    Disconnected(String),
+}
+
+/// Things typed locally, sent in order so a stale draft never lands after its own submit.
+#[derive(Debug)]
+pub enum Outgoing {
+    Typing(String),
+    Submit(String),
+}
+
+#[derive(Serialize)]
+struct LiveTypingBody<'a> {
+    message: &'a str,
+    seq: u64,
+}
+
+#[derive(Serialize)]
+struct SubmitBody<'a> {
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+struct SetNameBody<'a> {
+    name: &'a str,
+    invite: &'a str,
+}
+
+#[derive(Clone, Debug)]
+pub struct RoomClient {
+    http: Client,
+    origin: Url,
+    // /room/<id> or /watch/<spectator key>
+    room_path: String,
+    invite: String,
+    cookie: String,
+    pub spectator: bool,
+    pub encrypted: bool,
+    pub title: String,
+    pub topic: Option<String>,
+}
+
+impl RoomClient {
+    /// Loads the room page like a browser would, which is also where the connection cookie comes from.
+    pub async fn join(link: &str) -> Result<Self, ClientError> {
+        let url = Url::parse(link).map_err(|_| ClientError::BadUrl(link.to_string()))?;
+        let segments: Vec<&str> = url.path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        let (spectator, key) = match segments.as_slice() {
+            ["room", room_id] => (false, *room_id),
+            ["watch", spectator_key] => (true, *spectator_key),
+            _ => return Err(ClientError::BadUrl(link.to_string())),
+        };
+        let room_path = format!("/{}/{}", if spectator { "watch" } else { "room" }, key);
+        let invite = url.query_pairs()
+            .find(|(key, _)| key == "invite")
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_default();
+
+        let http = Client::new();
+        let response = http.get(url.clone()).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => return Err(ClientError::RoomNotFound),
+            StatusCode::FORBIDDEN => return Err(ClientError::InviteRequired),
+            status if !status.is_success() => return Err(ClientError::Status(status)),
+            _ => {},
+        }
+        // spectator links for rooms that are gone redirect home
+        if !response.url().path().starts_with(&room_path) {
+            return Err(ClientError::RoomNotFound);
+        }
+
+        let cookie = response.headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| value.split(';').next())
+            .find(|pair| pair.starts_with("impermachat_id="))
+            .map(str::to_string)
+            .ok_or(ClientError::NoCookie)?;
+
+        let page = html::parse(&response.text().await?);
+        let title = page.find_id("header-title")
+            .map(|title| title.text())
+            .unwrap_or_else(|| key.to_string());
+        let encrypted = page.find_class("e2e-banner").is_some();
+        let topic = page.find_id("room-topic")
+            .map(|topic| topic.text())
+            .filter(|topic| !topic.is_empty());
+
+        let mut origin = url;
+        origin.set_path("/");
+        origin.set_query(None);
+        origin.set_fragment(None);
+
+        Ok(Self {
+            http,
+            origin,
+            room_path,
+            invite,
+            cookie,
+            spectator,
+            encrypted,
+            title,
+            topic,
+        })
+    }
+
+    fn url(&self, path: &str) -> Url {
+        let mut url = self.origin.clone();
+        url.set_path(&format!("{}{}", self.room_path, path));
+        url
+    }
+
+    /// Follows the room's event stream until it ends, forwarding every datastar event.
+    pub async fn listen(self, updates: mpsc::UnboundedSender<Update>) {
+        let mut url = self.url("/connect");
+        if !self.spectator {
+            url.query_pairs_mut().append_pair("invite", &self.invite);
+        }
+
+        let response = match self.http.get(url).header(COOKIE, &self.cookie).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                let _ = updates.send(Update::Disconnected(e.to_string()));
+                return;
+            },
+        };
+
+        let mut parser = SseParser::default();
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = updates.send(Update::Disconnected(e.to_string()));
+                    return;
+                },
+            };
+            for update in parser.push(&chunk) {
+                if updates.send(update).is_err() {
+                    return;
+                }
+            }
+        }
+        let _ = updates.send(Update::Disconnected("the room closed the connection".to_string()));
+    }
+
+    pub async fn set_name(&self, name: &str) -> Result<Vec<Update>, ClientError> {
+        let response = self.http.post(self.url("/name"))
+            .header(COOKIE, &self.cookie)
+            .json(&SetNameBody { name, invite: &self.invite })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+
+        // name errors come back as fragments in the response itself
+        let mut parser = SseParser::default();
+        let mut updates = parser.push(&response.bytes().await?);
+        updates.extend(parser.push(b"\n\n"));
+        Ok(updates)
+    }
+
+    /// Sends drafts and messages one at a time, skipping drafts that were already replaced.
+    pub async fn send(self, mut outgoing: mpsc::UnboundedReceiver<Outgoing>, updates: mpsc::UnboundedSender<Update>) {
+        let mut seq = 0;
+        let mut next = outgoing.recv().await;
+        while let Some(item) = next.take() {
+            let result = match item {
+                Outgoing::Typing(mut message) => {
+                    // only the newest buffer matters, stop early if a submit is queued behind it
+                    loop {
+                        match outgoing.try_recv() {
+                            Ok(Outgoing::Typing(newer)) => message = newer,
+                            Ok(submit) => {
+                                next = Some(submit);
+                                break;
+                            },
+                            Err(_) => break,
+                        }
+                    }
+                    seq += 1;
+                    self.http.post(self.url("/live"))
+                        .header(COOKIE, &self.cookie)
+                        .json(&LiveTypingBody { message: &message, seq })
+                        .send()
+                        .await
+                },
+                Outgoing::Submit(message) => {
+                    self.http.post(self.url("/submit"))
+                        .header(COOKIE, &self.cookie)
+                        .json(&SubmitBody { message: &message })
+                        .send()
+                        .await
+                },
+            };
+
+            match result {
+                Ok(response) if !response.status().is_success() => {
+                    let _ = updates.send(Update::Fragment(format!(
+                        "<p class=\"error-message\">The server refused that ({})</p>",
+                        response.status(),
+                    )));
+                },
+                Ok(_) => {},
+                Err(e) => {
+                    let _ = updates.send(Update::Fragment(format!(
+                        "<p class=\"error-message\">Couldn't reach the server: {}</p>",
+                        e,
+                    )));
+                },
+            }
+
+            if next.is_none() {
+                next = outgoing.recv().await;
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<Update> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut updates = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|window| window == b"\n\n") {
+            let raw: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            if let Some(update) = parse_event(&String::from_utf8_lossy(&raw)) {
+                updates.push(update);
+            }
+        }
+        updates
+    }
+}
+
+fn parse_event(raw: &str) -> Option<Update> {
+    let mut kind = "";
+    let mut data = Vec::new();
+    for line in raw.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            kind = value.trim();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+
+    match kind {
+        "datastar-merge-fragments" => {
+            let fragment = data.iter()
+                .map(|line| {
+                    let mut line = *line;
+                    while let Some(rest) = line.strip_prefix("fragments ") {
+                        line = rest;
+                    }
+                    line
+                })
+                .collect::<Vec<&str>>()
+                .join("\n");
+            Some(Update::Fragment(fragment))
+        },
+        "datastar-merge-signals" => Some(Update::Signals(
+            data.iter()
+                .map(|line| line.strip_prefix("signals ").unwrap_or(line))
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        )),
+        _ => None,
+    }
+}
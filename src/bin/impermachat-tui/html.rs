@@ -0,0 +1,264 @@
+// Just enough HTML to read the fragments the server renders from its own templates.
+
+const VOID_ELEMENTS: [&str; 6] = ["br", "input", "img", "meta", "link", "hr"];
+
+#[derive(Clone, Debug)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Element {
+    pub tag: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.attribute("id")
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attribute("class")
+            .is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    // depth first, including self
+    pub fn find(&self, predicate: &dyn Fn(&Element) -> bool) -> Option<&Element> {
+        if predicate(self) {
+            return Some(self);
+        }
+        self.elements().find_map(|child| child.find(predicate))
+    }
+
+    pub fn find_all<'a>(&'a self, predicate: &dyn Fn(&Element) -> bool, found: &mut Vec<&'a Element>) {
+        if predicate(self) {
+            found.push(self);
+        }
+        for child in self.elements() {
+            child.find_all(predicate, found);
+        }
+    }
+
+    pub fn find_id(&self, id: &str) -> Option<&Element> {
+        self.find(&|element| element.id() == Some(id))
+    }
+
+    pub fn find_class(&self, class: &str) -> Option<&Element> {
+        self.find(&|element| element.has_class(class))
+    }
+
+    // text content with <br> as newlines and template indentation squashed
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.collect_text(&mut text);
+        text.lines()
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+            .join("\n")
+            .trim()
+            .to_string()
+    }
+
+    // only the text directly inside this element, skipping buttons and badges
+    pub fn own_text(&self) -> String {
+        self.children.iter()
+            .filter_map(|child| match child {
+                Node::Text(text) => Some(text.trim()),
+                Node::Element(_) => None,
+            })
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    fn collect_text(&self, text: &mut String) {
+        for child in &self.children {
+            match child {
+                Node::Text(content) => text.push_str(content),
+                Node::Element(element) if element.tag == "br" => text.push('\n'),
+                Node::Element(element) if matches!(element.tag.as_str(), "script" | "style" | "button") => {},
+                Node::Element(element) => element.collect_text(text),
+            }
+        }
+    }
+}
+
+/// Parses a fragment into a synthetic root element so several top level nodes can share it.
+pub fn parse(source: &str) -> Element {
+    let mut stack = vec![Element {
+        tag: "#root".to_string(),
+        ..Element::default()
+    }];
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            push_text(&mut stack, rest);
+            break;
+        };
+        push_text(&mut stack, &rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = tag_end(rest) else {
+            // unterminated tag, treat the rest as text
+            push_text(&mut stack, rest);
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            close(&mut stack, name.trim());
+        } else if !tag.starts_with('!') {
+            let self_closing = tag.ends_with('/');
+            let element = parse_tag(tag.trim_end_matches('/'));
+            if self_closing || VOID_ELEMENTS.contains(&element.tag.as_str()) {
+                append(&mut stack, Node::Element(element));
+            } else {
+                stack.push(element);
+            }
+        }
+    }
+
+    while stack.len() > 1 {
+        let element = stack.pop().expect("Checked above");
+        append(&mut stack, Node::Element(element));
+    }
+    stack.pop().expect("Root is never popped")
+}
+
+// the closing > of a tag, ignoring any inside quoted attribute values
+fn tag_end(source: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in source.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return Some(index),
+            _ => {},
+        }
+    }
+    None
+}
+
+fn parse_tag(tag: &str) -> Element {
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let mut element = Element {
+        tag: tag[..name_end].to_lowercase(),
+        ..Element::default()
+    };
+
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(rest.len());
+        let key = rest[..key_end].to_string();
+        rest = rest[key_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after_equals) = rest.strip_prefix('=') {
+            let after_equals = after_equals.trim_start();
+            match after_equals.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after_equals[1..];
+                    let value_end = inner.find(quote).unwrap_or(inner.len());
+                    value = decode_entities(&inner[..value_end]);
+                    rest = inner.get(value_end + 1..).unwrap_or("");
+                },
+                _ => {
+                    let value_end = after_equals.find(char::is_whitespace).unwrap_or(after_equals.len());
+                    value = decode_entities(&after_equals[..value_end]);
+                    rest = &after_equals[value_end..];
+                },
+            }
+        }
+        if !key.is_empty() {
+            element.attributes.push((key, value));
+        }
+        rest = rest.trim_start();
+    }
+    element
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+    if !text.is_empty() {
+        append(stack, Node::Text(decode_entities(text)));
+    }
+}
+
+fn append(stack: &mut [Element], node: Node) {
+    stack.last_mut()
+        .expect("Root is never popped")
+        .children
+        .push(node);
+}
+
+fn close(stack: &mut Vec<Element>, name: &str) {
+    // stray closing tags are dropped, unclosed ones are closed on the way out
+    let Some(position) = stack.iter().rposition(|element| element.tag == name) else {
+        return;
+    };
+    if position == 0 {
+        return;
+    }
+    while stack.len() > position {
+        let element = stack.pop().expect("Checked above");
+        append(stack, Node::Element(element));
+    }
+}
+
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';').filter(|end| *end <= 10) else {
+            decoded.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity.strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
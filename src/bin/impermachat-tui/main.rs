@@ -0,0 +1,254 @@
+//! Terminal client for Impermachat rooms.
+//!
+//! `impermachat-tui https://host/room/name [--name you]` joins the room through the same
+//! endpoints the browser page uses and shows the chat log next to everyone's live drafts.
+
+mod client;
+mod html;
+mod room;
+mod ui;
+
+use std::{env, process, thread};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    DefaultTerminal,
+};
+use tokio::sync::mpsc;
+
+use client::{Outgoing, RoomClient, Update};
+use room::{RoomView, Seat};
+
+// same limit as the browser's textarea
+const MAX_INPUT_LENGTH: usize = 4000;
+const SCROLL_STEP: u16 = 5;
+
+pub struct App {
+    client: RoomClient,
+    view: RoomView,
+    input: Vec<char>,
+    cursor: usize,
+    // rows scrolled up from the newest message
+    scroll: u16,
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+    updates: mpsc::UnboundedSender<Update>,
+    wanted_name: Option<String>,
+    quit: bool,
+}
+
+impl App {
+    fn input_text(&self) -> String {
+        self.input.iter().collect()
+    }
+
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Fragment(fragment) => {
+                let was_choosing = self.view.seat == Seat::ChoosingName;
+                self.view.apply_fragment(&fragment);
+                match &self.view.seat {
+                    Seat::ChoosingName if !was_choosing => {
+                        // --name fills in the prompt the first time it shows up
+                        if let Some(name) = self.wanted_name.take() {
+                            self.input = name.chars().collect();
+                            self.cursor = self.input.len();
+                            self.claim_name();
+                        }
+                    },
+                    Seat::Seated(_) if was_choosing => {
+                        self.input.clear();
+                        self.cursor = 0;
+                        self.view.status = None;
+                    },
+                    _ => {},
+                }
+            },
+            Update::Signals(signals) => self.view.apply_signals(&signals),
+            Update::Disconnected(reason) => {
+                if !matches!(self.view.seat, Seat::Closed(_)) {
+                    self.view.seat = Seat::Closed(format!("Disconnected: {}", reason));
+                }
+            },
+        }
+    }
+
+    fn can_type(&self) -> bool {
+        match self.view.seat {
+            Seat::ChoosingName => true,
+            Seat::Seated(_) => !self.client.encrypted,
+            _ => false,
+        }
+    }
+
+    fn claim_name(&self) {
+        let name = self.input_text().trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+        let client = self.client.clone();
+        let updates = self.updates.clone();
+        tokio::spawn(async move {
+            match client.set_name(&name).await {
+                Ok(responses) => {
+                    for update in responses {
+                        let _ = updates.send(update);
+                    }
+                },
+                Err(e) => {
+                    let _ = updates.send(Update::Fragment(format!("<p class=\"error-message\">{}</p>", e)));
+                },
+            }
+        });
+    }
+
+    fn typed(&self) {
+        if matches!(self.view.seat, Seat::Seated(_)) {
+            let _ = self.outgoing.send(Outgoing::Typing(self.input_text()));
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_add(SCROLL_STEP),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(SCROLL_STEP),
+            _ if !self.can_type() => {},
+            KeyCode::Enter if key.modifiers.intersects(KeyModifiers::ALT | KeyModifiers::SHIFT) => {
+                self.insert('\n');
+            },
+            KeyCode::Char('j') if key.modifiers.contains(KeyModifiers::CONTROL) => self.insert('\n'),
+            KeyCode::Enter => match self.view.seat {
+                Seat::ChoosingName => self.claim_name(),
+                _ => {
+                    let message = self.input_text();
+                    if !message.trim().is_empty() {
+                        let _ = self.outgoing.send(Outgoing::Submit(message));
+                        self.input.clear();
+                        self.cursor = 0;
+                        self.scroll = 0;
+                    }
+                },
+            },
+            KeyCode::Char(c) => self.insert(c),
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.input.remove(self.cursor);
+                self.typed();
+            },
+            KeyCode::Delete if self.cursor < self.input.len() => {
+                self.input.remove(self.cursor);
+                self.typed();
+            },
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.input.len()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.input.len(),
+            _ => {},
+        }
+    }
+
+    fn insert(&mut self, c: char) {
+        if self.input.len() >= MAX_INPUT_LENGTH {
+            return;
+        }
+        self.input.insert(self.cursor, c);
+        self.cursor += 1;
+        self.typed();
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: impermachat-tui <room link> [--name <name>]");
+    process::exit(2);
+}
+
+#[tokio::main]
+async fn main() {
+    let mut link = None;
+    let mut wanted_name = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" | "-n" => wanted_name = Some(args.next().unwrap_or_else(|| usage())),
+            "--help" | "-h" => usage(),
+            _ if link.is_none() => link = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(link) = link else { usage() };
+
+    let client = match RoomClient::join(&link).await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Couldn't join {}: {}", link, e);
+            process::exit(1);
+        },
+    };
+
+    let (updates_tx, updates_rx) = mpsc::unbounded_channel();
+    let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+    tokio::spawn(client.clone().listen(updates_tx.clone()));
+    tokio::spawn(client.clone().send(outgoing_rx, updates_tx.clone()));
+
+    let app = App {
+        view: RoomView::new(client.title.clone(), client.topic.clone()),
+        client,
+        input: Vec::new(),
+        cursor: 0,
+        scroll: 0,
+        outgoing: outgoing_tx,
+        updates: updates_tx,
+        wanted_name,
+        quit: false,
+    };
+
+    let terminal = ratatui::init();
+    let result = run(terminal, app, updates_rx).await;
+    ratatui::restore();
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+async fn run(
+    mut terminal: DefaultTerminal,
+    mut app: App,
+    mut updates: mpsc::UnboundedReceiver<Update>,
+) -> std::io::Result<()> {
+    // crossterm's reader blocks, so keys come in from their own thread
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if keys_tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    while !app.quit {
+        terminal.draw(|frame| ui::draw(frame, &app))?;
+
+        tokio::select! {
+            Some(update) = updates.recv() => {
+                app.apply(update);
+                // a burst of typing frames only needs one redraw
+                while let Ok(update) = updates.try_recv() {
+                    app.apply(update);
+                }
+            },
+            Some(event) = keys.recv() => {
+                if let Event::Key(key) = event {
+                    app.handle_key(key);
+                }
+            },
+            else => break,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use ratatui::style::Color;
+
+use crate::html::{self, Element};
+
+#[derive(Clone, Debug)]
+pub struct ChatMessage {
+    pub name: String,
+    pub color: Color,
+    pub content: String,
+    // someone @mentioned us in it
+    pub mentioned: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Draft {
+    // the typing box's element id, stable per typist
+    pub id: String,
+    pub name: String,
+    pub color: Color,
+    pub content: String,
+    pub idle: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Seat {
+    Connecting,
+    ChoosingName,
+    Seated(String),
+    // the server's "#n in line" message
+    Waiting(String),
+    Spectating,
+    Closed(String),
+}
+
+/// Everything the terminal shows, rebuilt from the same fragments the browser merges.
+#[derive(Debug)]
+pub struct RoomView {
+    pub title: String,
+    pub topic: Option<String>,
+    // "name: content" for each pinned message
+    pub pinned: Vec<String>,
+    // one summary line per poll, voting goes through /vote
+    pub polls: Vec<String>,
+    pub remaining: String,
+    pub banner: Option<String>,
+    pub status: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    pub drafts: Vec<Draft>,
+    pub seat: Seat,
+}
+
+impl RoomView {
+    pub fn new(title: String, topic: Option<String>) -> Self {
+        Self {
+            title,
+            topic,
+            pinned: Vec::new(),
+            polls: Vec::new(),
+            remaining: String::new(),
+            banner: None,
+            status: None,
+            messages: Vec::new(),
+            drafts: Vec::new(),
+            seat: Seat::Connecting,
+        }
+    }
+
+    pub fn apply_fragment(&mut self, fragment: &str) {
+        let root = html::parse(fragment);
+        for element in root.elements() {
+            self.merge(element);
+        }
+    }
+
+    pub fn apply_signals(&mut self, signals: &str) {
+        // datastar signals are JS object literals, not JSON, so pick out the one we show
+        if let Some(start) = signals.find("remaining: '") {
+            let value = &signals[start + "remaining: '".len()..];
+            if let Some(end) = value.find('\'') {
+                self.remaining = value[..end].to_string();
+            }
+        }
+    }
+
+    fn merge(&mut self, element: &Element) {
+        let id = element.id().unwrap_or_default();
+        match id {
+            "message-list" => {
+                let mut boxes = Vec::new();
+                element.find_all(&|e| e.id().is_some_and(|id| id.starts_with("message-")), &mut boxes);
+                self.messages = boxes.into_iter().map(chat_message).collect();
+            },
+            "typing" => {
+                let mut boxes = Vec::new();
+                element.find_all(&|e| e.id().is_some_and(|id| id.starts_with("typing-")), &mut boxes);
+                self.drafts = boxes.into_iter().map(draft).collect();
+            },
+            _ if id.starts_with("typing-") => {
+                let updated = draft(element);
+                match self.drafts.iter_mut().find(|draft| draft.id == updated.id) {
+                    Some(existing) => *existing = updated,
+                    None => self.drafts.push(updated),
+                }
+            },
+            "chat-control" => self.seat = seat(element),
+            "set-name-status" => {
+                self.status = Some(element.text()).filter(|status| !status.is_empty());
+            },
+            "chat-container" => self.seat = Seat::Closed(element.text()),
+            "command-notice" => {
+                let mut lines = Vec::new();
+                element.find_all(&|e| e.tag == "p", &mut lines);
+                let notice = lines.into_iter().map(Element::text).collect::<Vec<_>>().join(" · ");
+                self.status = Some(notice).filter(|notice| !notice.is_empty());
+            },
+            "room-topic" => self.topic = Some(element.text()).filter(|topic| !topic.is_empty()),
+            "pinned" => {
+                let mut pins = Vec::new();
+                element.find_all(&|e| e.has_class("pinned-message"), &mut pins);
+                self.pinned = pins.into_iter()
+                    .filter_map(|pin| pin.find(&|e| e.tag == "a"))
+                    .map(body_text)
+                    .collect();
+            },
+            "polls" => {
+                let mut polls = Vec::new();
+                element.find_all(&|e| e.has_class("poll"), &mut polls);
+                self.polls = polls.into_iter().map(poll_summary).collect();
+            },
+            "expiry-warning" => {
+                self.banner = Some(element.text()).filter(|banner| !banner.is_empty());
+            },
+            _ if element.has_class("error-message") => self.status = Some(element.text()),
+            // search, replay and owner panels have no terminal equivalent
+            _ => {},
+        }
+    }
+}
+
+fn seat(chat_control: &Element) -> Seat {
+    if chat_control.find_id("set-name").is_some() {
+        return Seat::ChoosingName;
+    }
+
+    let text = chat_control.find(&|e| e.tag == "p")
+        .map(Element::text)
+        .unwrap_or_default();
+    if chat_control.find_id("room-input").is_some() {
+        let name = text.strip_prefix("Speaking as ")
+            .unwrap_or(&text)
+            .trim_end_matches(" (encrypted)")
+            .to_string();
+        Seat::Seated(name)
+    } else if text.contains("in line") {
+        Seat::Waiting(text)
+    } else {
+        Seat::Spectating
+    }
+}
+
+fn chat_message(message_box: &Element) -> ChatMessage {
+    let titlebar = message_box.find_class("titlebar");
+    ChatMessage {
+        name: titlebar.map(Element::own_text).unwrap_or_default(),
+        color: titlebar.map(background_color).unwrap_or(Color::Reset),
+        content: body_text(message_box),
+        mentioned: message_box.has_class("mentioned"),
+    }
+}
+
+fn draft(typing_box: &Element) -> Draft {
+    let titlebar = typing_box.find_class("titlebar");
+    Draft {
+        id: typing_box.id().unwrap_or_default().to_string(),
+        name: titlebar.map(Element::own_text).unwrap_or_default(),
+        color: titlebar.map(background_color).unwrap_or(Color::Reset),
+        content: body_text(typing_box),
+        idle: typing_box.find_class("idle-badge").is_some(),
+    }
+}
+
+// "Lunch? 1. Pizza 2 · 2. Tacos 1", marked when it's closed
+fn poll_summary(poll: &Element) -> String {
+    let question = poll.find(&|e| e.tag == "strong").map(Element::text).unwrap_or_default();
+    let mut options = Vec::new();
+    poll.find_all(&|e| e.has_class("poll-option"), &mut options);
+    let counts: Vec<String> = options.into_iter()
+        .map(|option| {
+            let label = option.find_class("poll-label").map(Element::text).unwrap_or_default();
+            let count = option.find_class("poll-count").map(Element::text).unwrap_or_default();
+            format!("{} {}", label, count)
+        })
+        .collect();
+    let closed = if poll.has_class("closed") { " (closed)" } else { "" };
+    format!("{}  {}{}", question, counts.join(" · "), closed)
+}
+
+// everything in a message box except its titlebar
+fn body_text(message_box: &Element) -> String {
+    if message_box.find_class("e2e-cipher").is_some() {
+        return "[encrypted]".to_string();
+    }
+    let mut body = message_box.clone();
+    body.children.retain(|child| !matches!(child, html::Node::Element(e) if e.has_class("titlebar")));
+    body.text()
+}
+
+fn background_color(titlebar: &Element) -> Color {
+    titlebar.attribute("style")
+        .and_then(|style| style.split("background-color:").nth(1))
+        .map(|value| value.trim().trim_end_matches(';').trim())
+        .and_then(|hex| hex.strip_prefix('#'))
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .map(|rgb| Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+        .unwrap_or(Color::Reset)
+}
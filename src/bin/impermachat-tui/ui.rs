@@ -0,0 +1,197 @@
+use ratatui::{
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::{
+    room::{RoomView, Seat},
+    App,
+};
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let view = &app.view;
+    let banner_height = if view.banner.is_some() { 3 } else { 0 };
+    let [header, banner, pinned, body, input, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(banner_height),
+        Constraint::Length((view.pinned.len() + view.polls.len()) as u16),
+        Constraint::Min(5),
+        Constraint::Length(input_height(app)),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    frame.render_widget(
+        Line::from(vec![
+            Span::styled(view.title.clone(), Style::new().bold()),
+            Span::raw("  "),
+            Span::styled(view.topic.clone().unwrap_or_default(), Style::new().italic()),
+            Span::raw("  "),
+            Span::styled(view.remaining.clone(), Style::new().dim()),
+        ]),
+        header,
+    );
+
+    frame.render_widget(
+        Text::from_iter(view.pinned.iter().map(|pin| Line::from(vec![
+            Span::styled("Pinned ", Style::new().yellow().bold()),
+            Span::raw(pin.replace('\n', " ")),
+        ])).chain(view.polls.iter().map(|poll| Line::from(vec![
+            Span::styled("Poll ", Style::new().cyan().bold()),
+            Span::raw(poll.clone()),
+        ])))),
+        pinned,
+    );
+
+    if let Some(text) = &view.banner {
+        frame.render_widget(
+            Paragraph::new(text.as_str())
+                .wrap(Wrap { trim: true })
+                .block(Block::bordered().border_style(Style::new().yellow()))
+                .yellow(),
+            banner,
+        );
+    }
+
+    let [log, typing] = Layout::horizontal([
+        Constraint::Percentage(60),
+        Constraint::Percentage(40),
+    ]).areas(body);
+    draw_log(frame, app, log);
+    draw_typing(frame, view, typing);
+    draw_input(frame, app, input);
+
+    let help = match &view.seat {
+        Seat::Closed(_) => "Esc to leave",
+        Seat::ChoosingName => "Enter to claim the name · Esc to leave",
+        Seat::Seated(_) => "Enter to send · Alt+Enter for a new line · PgUp/PgDn to scroll · Esc to leave",
+        _ => "PgUp/PgDn to scroll · Esc to leave",
+    };
+    let status = view.status.as_deref().map(|status| Span::styled(format!("{}  ", status), Style::new().red()));
+    frame.render_widget(
+        Line::from_iter(status.into_iter().chain([Span::styled(help, Style::new().dim())])),
+        footer,
+    );
+}
+
+fn draw_log(frame: &mut Frame, app: &App, area: Rect) {
+    let mut lines = Vec::new();
+    for message in &app.view.messages {
+        // system lines and /me have no titlebar, the name is part of the text
+        if message.name.is_empty() {
+            let style = if message.mentioned { Style::new().italic().yellow() } else { Style::new().italic().dim() };
+            lines.push(Line::from(Span::styled(message.content.trim().to_string(), style)));
+            lines.push(Line::default());
+            continue;
+        }
+        let mut title = vec![Span::styled(
+            message.name.clone(),
+            Style::new().fg(message.color).add_modifier(Modifier::BOLD),
+        )];
+        if message.mentioned {
+            title.push(Span::styled("  mentioned you", Style::new().yellow().bold()));
+        }
+        lines.push(Line::from(title));
+        lines.extend(message.content.lines().map(|line| Line::from(line.to_string())));
+        lines.push(Line::default());
+    }
+
+    // stick to the newest messages unless the reader scrolled back
+    let inner = Block::bordered().inner(area);
+    let rows = wrapped_rows(&lines, inner.width);
+    let bottom = rows.saturating_sub(inner.height as usize);
+    let scroll = bottom.saturating_sub(app.scroll as usize);
+
+    frame.render_widget(
+        Paragraph::new(Text::from(lines))
+            .wrap(Wrap { trim: false })
+            .scroll((scroll.min(u16::MAX as usize) as u16, 0))
+            .block(Block::bordered().title(" Chat log ")),
+        area,
+    );
+}
+
+fn draw_typing(frame: &mut Frame, view: &RoomView, area: Rect) {
+    let block = Block::bordered().title(" People typing ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let drafts: Vec<_> = view.drafts.iter().filter(|draft| !draft.content.is_empty() || draft.idle).collect();
+    if drafts.is_empty() {
+        return;
+    }
+
+    // split the pane evenly, a draft box needs at least its borders and a line
+    let height = (inner.height / drafts.len() as u16).max(3);
+    for (index, draft) in drafts.iter().enumerate() {
+        let top = inner.y + index as u16 * height;
+        if top + 3 > inner.y + inner.height {
+            break;
+        }
+        let area = Rect {
+            y: top,
+            height: height.min(inner.y + inner.height - top),
+            ..inner
+        };
+
+        let mut title = vec![Span::styled(format!(" {} ", draft.name), Style::new().fg(draft.color).bold())];
+        if draft.idle {
+            title.push(Span::styled("idle ", Style::new().dim()));
+        }
+        let inner_box = Block::new().borders(Borders::ALL).inner(area);
+        let lines: Vec<Line> = draft.content.lines().map(|line| Line::from(line.to_string())).collect();
+        // show the end of long drafts, that's where the typing happens
+        let scroll = wrapped_rows(&lines, inner_box.width).saturating_sub(inner_box.height as usize);
+        frame.render_widget(
+            Paragraph::new(Text::from(lines))
+                .wrap(Wrap { trim: false })
+                .scroll((scroll.min(u16::MAX as usize) as u16, 0))
+                .style(if draft.idle { Style::new().dim() } else { Style::new() })
+                .block(Block::bordered().title(Line::from(title))),
+            area,
+        );
+    }
+}
+
+fn draw_input(frame: &mut Frame, app: &App, area: Rect) {
+    let (title, editable) = match &app.view.seat {
+        Seat::Connecting => (" Connecting… ".to_string(), false),
+        Seat::ChoosingName => (" Choose a name ".to_string(), true),
+        Seat::Seated(name) if app.client.encrypted => (format!(" {} (encrypted rooms can only be read here) ", name), false),
+        Seat::Seated(name) => (format!(" Speaking as {} ", name), true),
+        Seat::Waiting(message) => (format!(" {} ", message), false),
+        Seat::Spectating => (" Watching as a spectator ".to_string(), false),
+        Seat::Closed(reason) => (format!(" {} ", reason.replace('\n', " ")), false),
+    };
+
+    let border = if editable { Style::new() } else { Style::new().fg(Color::DarkGray) };
+    let block = Block::bordered().title(title).border_style(border);
+    let inner = block.inner(area);
+    let text: String = app.input.iter().collect();
+    frame.render_widget(Paragraph::new(text.as_str()).block(block), area);
+
+    if editable {
+        // the cursor sits on its line and column within the unwrapped input
+        let before: String = app.input[..app.cursor].iter().collect();
+        let row = before.matches('\n').count() as u16;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() as u16;
+        frame.set_cursor_position(Position {
+            x: (inner.x + column).min(inner.x + inner.width.saturating_sub(1)),
+            y: (inner.y + row).min(inner.y + inner.height.saturating_sub(1)),
+        });
+    }
+}
+
+fn input_height(app: &App) -> u16 {
+    let lines = app.input.iter().filter(|c| **c == '\n').count() as u16 + 1;
+    lines.clamp(1, 6) + 2
+}
+
+fn wrapped_rows(lines: &[Line], width: u16) -> usize {
+    let width = width.max(1) as usize;
+    lines.iter()
+        .map(|line| line.width().div_ceil(width).max(1))
+        .sum()
+}
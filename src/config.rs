@@ -0,0 +1,109 @@
+use std::{
+    env,
+    str::FromStr,
+    time::Duration,
+};
+
+/// Server settings, read from `IMPERMACHAT_*` environment variables with sensible defaults.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// How often coalesced typing updates are pushed to a room (`IMPERMACHAT_TYPING_HZ`).
+    pub typing_hz: u32,
+    /// Seconds without typing before a draft gets an idle badge (`IMPERMACHAT_TYPING_IDLE_SECS`).
+    pub typing_idle_secs: u64,
+    /// Seconds without typing before a draft is cleared (`IMPERMACHAT_TYPING_CLEAR_SECS`).
+    pub typing_clear_secs: u64,
+    /// Seconds before expiry at which rooms get a warning banner (`IMPERMACHAT_EXPIRY_WARNINGS`, comma separated).
+    pub expiry_warning_secs: Vec<u64>,
+    /// Seconds a room stays open after its time runs out so the owner can still extend it (`IMPERMACHAT_EXPIRY_GRACE_SECS`).
+    pub expiry_grace_secs: u64,
+    /// URLs sent every room's events (`IMPERMACHAT_WEBHOOK_URLS`, comma separated).
+    pub webhook_urls: Vec<String>,
+    /// Key the global webhooks are signed with, they're skipped without one (`IMPERMACHAT_WEBHOOK_SECRET`).
+    pub webhook_secret: Option<String>,
+    /// Whether rooms created through the API may name their own webhook URL (`IMPERMACHAT_ROOM_WEBHOOKS`).
+    /// Off by default since it lets anyone make the server send requests.
+    pub room_webhooks: bool,
+    /// Rooms each address can create through the JSON API per minute, 0 for no limit (`IMPERMACHAT_API_ROOMS_PER_MINUTE`).
+    pub api_rooms_per_minute: usize,
+    /// Address for the IRC gateway, which only runs when this is set (`IMPERMACHAT_IRC_LISTEN`, like `127.0.0.1:6667`).
+    pub irc_listen: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            typing_hz: 20,
+            typing_idle_secs: 30,
+            typing_clear_secs: 120,
+            expiry_warning_secs: vec![300, 60, 10],
+            expiry_grace_secs: 30,
+            webhook_urls: Vec::new(),
+            webhook_secret: None,
+            room_webhooks: false,
+            api_rooms_per_minute: 10,
+            irc_listen: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            typing_hz: env_or("IMPERMACHAT_TYPING_HZ", defaults.typing_hz).clamp(1, 120),
+            typing_idle_secs: env_or("IMPERMACHAT_TYPING_IDLE_SECS", defaults.typing_idle_secs),
+            typing_clear_secs: env_or("IMPERMACHAT_TYPING_CLEAR_SECS", defaults.typing_clear_secs),
+            expiry_warning_secs: env_list_or("IMPERMACHAT_EXPIRY_WARNINGS", defaults.expiry_warning_secs),
+            expiry_grace_secs: env_or("IMPERMACHAT_EXPIRY_GRACE_SECS", defaults.expiry_grace_secs),
+            webhook_urls: env_list_or("IMPERMACHAT_WEBHOOK_URLS", defaults.webhook_urls),
+            webhook_secret: env::var("IMPERMACHAT_WEBHOOK_SECRET").ok().filter(|secret| !secret.is_empty()),
+            room_webhooks: env_or("IMPERMACHAT_ROOM_WEBHOOKS", defaults.room_webhooks),
+            api_rooms_per_minute: env_or("IMPERMACHAT_API_ROOMS_PER_MINUTE", defaults.api_rooms_per_minute),
+            irc_listen: env::var("IMPERMACHAT_IRC_LISTEN").ok().filter(|address| !address.is_empty()),
+        }
+    }
+
+    pub fn typing_idle_after(&self) -> Duration {
+        Duration::from_secs(self.typing_idle_secs)
+    }
+
+    // never clear a draft before it has had a chance to show as idle
+    pub fn typing_clear_after(&self) -> Duration {
+        Duration::from_secs(self.typing_clear_secs.max(self.typing_idle_secs))
+    }
+
+    pub fn typing_frame_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.typing_hz
+    }
+
+    pub fn expiry_grace(&self) -> Duration {
+        Duration::from_secs(self.expiry_grace_secs)
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            println!("Ignoring invalid {}={}", name, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+// an empty value turns the list off entirely
+fn env_list_or<T: FromStr>(name: &str, default: Vec<T>) -> Vec<T> {
+    match env::var(name) {
+        Ok(value) => value.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<T>, _>>()
+            .unwrap_or_else(|_| {
+                println!("Ignoring invalid {}={}", name, value);
+                default
+            }),
+        Err(_) => default,
+    }
+}
@@ -1,3 +1,4 @@
 pub mod handlers;
 pub mod routes;
 pub mod templates;
+pub mod slugs;
@@ -1,19 +1,68 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
 use axum::{
+    extract::{
+        ConnectInfo,
+        State,
+    },
+    http::{
+        HeaderMap,
+        StatusCode,
+        header::SET_COOKIE,
+    },
     response::{IntoResponse, Redirect},
     Form,
+    Json,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::rooms::{
+    handlers::{
+        AllRooms,
+        MAX_TITLE_LENGTH,
+        RoomSettings,
+    },
+    utils::{
+        get_cookie,
+        random_token,
+    },
 };
-use serde::Deserialize;
 
 use super::templates::IndexTemplate;
+use super::slugs::{
+    random_token_slug,
+    random_word_slug,
+};
+
+const CSRF_COOKIE: &str = "impermachat_csrf";
+// a room can't live longer than this anyway
+const MAX_IDLE_MINUTES: u64 = 13 * 60;
+// random slugs can land on a live room, roll again this many times before giving up
+const MAX_SLUG_ATTEMPTS: usize = 10;
+
+fn csrf_cookie(csrf_token: &str) -> String {
+    format!("{}={}; Path=/; HttpOnly; SameSite=Strict", CSRF_COOKIE, csrf_token)
+}
 
 pub mod get {
     use super::*;
 
-    pub async fn index() -> IndexTemplate {
-        IndexTemplate{
-            show_message: false,
-            message: "".to_string(),
-        }
+    pub async fn index(headers: HeaderMap) -> impl IntoResponse {
+        // double submit token, the form has to echo back what the cookie holds
+        let csrf_token = get_cookie(&headers, CSRF_COOKIE)
+            .filter(|token| !token.is_empty())
+            .unwrap_or_else(|| random_token(32));
+        (
+            [(SET_COOKIE, csrf_cookie(&csrf_token))],
+            IndexTemplate{
+                show_message: false,
+                message: "".to_string(),
+                csrf_token,
+            },
+        )
     }
 }
 
@@ -25,27 +74,249 @@ pub mod post {
         pub room_name: String,
         pub hours: u64,
         pub minutes: u64,
+        #[serde(default)]
+        pub max_participants: usize,
+        #[serde(default)]
+        pub slug_style: SlugStyle,
+        #[serde(default)]
+        pub csrf_token: String,
+        #[serde(default, deserialize_with = "checkbox")]
+        pub invite_only: bool,
+        #[serde(default, deserialize_with = "checkbox")]
+        pub encrypted: bool,
+        #[serde(default, deserialize_with = "checkbox")]
+        pub record_typing: bool,
+        #[serde(default, deserialize_with = "checkbox")]
+        pub hidden_typing: bool,
+        #[serde(default, deserialize_with = "checkbox")]
+        pub disable_export: bool,
+        // minutes, 0 keeps the room open until its time runs out
+        #[serde(default)]
+        pub close_when_empty_minutes: u64,
+        #[serde(default)]
+        pub close_when_quiet_minutes: u64,
+        // comma separated bot keys, like "dice,timer"
+        #[serde(default)]
+        pub bots: String,
+        #[serde(default)]
+        pub webhook_url: String,
+        #[serde(default)]
+        pub topic: String,
+    }
+
+    // html checkboxes submit "on" while the JSON API sends real booleans
+    fn checkbox<'de, D>(deserializer: D) -> Result<bool, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Checkbox {
+            Bool(bool),
+            String(String),
+        }
+
+        Ok(match Checkbox::deserialize(deserializer)? {
+            Checkbox::Bool(checked) => checked,
+            Checkbox::String(value) => matches!(value.as_str(), "on" | "true" | "1"),
+        })
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum SlugStyle {
+        // the room name doubles as the URL
+        #[default]
+        Name,
+        Words,
+        Token,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct CreatedRoom {
+        pub room_id: String,
+        pub title: String,
+        pub url: String,
+        pub creation_token: String,
+        // present when the room got its own webhook, deliveries to it are signed with this
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub webhook_secret: Option<String>,
+        // bearer token for POST /room/<id>/messages, missing for encrypted rooms
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub post_token: Option<String>,
     }
 
     fn sanitize_room_name(name: &str) -> String {
-        name.chars()
+        name.trim()
+            .chars()
+            .map(|c| if c.is_whitespace() { '-' } else { c })
             .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
             .collect::<String>()
             .to_lowercase()
     }
 
+    fn room_slug_and_settings(create_room_form: &CreateRoomForm) -> Option<(String, RoomSettings)> {
+        let title: String = create_room_form.room_name
+            .trim()
+            .chars()
+            .take(MAX_TITLE_LENGTH)
+            .collect();
+
+        let slug = match create_room_form.slug_style {
+            SlugStyle::Name => sanitize_room_name(&title),
+            SlugStyle::Words => random_word_slug(),
+            SlugStyle::Token => random_token_slug(),
+        };
+
+        if slug.is_empty() {
+            return None;
+        }
+
+        // random rooms without a name go by their slug, create_room fills that in once the slug is settled
+        Some((slug, RoomSettings {
+            // 0 means no limit
+            max_participants: Some(create_room_form.max_participants).filter(|max| *max > 0),
+            title,
+            invite_only: create_room_form.invite_only,
+            encrypted: create_room_form.encrypted,
+            record_typing: create_room_form.record_typing,
+            hidden_typing: create_room_form.hidden_typing,
+            exports_disabled: create_room_form.disable_export,
+            close_when_empty: idle_timeout(create_room_form.close_when_empty_minutes),
+            close_when_quiet: idle_timeout(create_room_form.close_when_quiet_minutes),
+            bots: create_room_form.bots
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .collect(),
+            webhook_url: create_room_form.webhook_url.clone(),
+            topic: create_room_form.topic.clone(),
+        }))
+    }
+
+    // a named room that already exists is left for the caller to join, a random slug that collides is rolled again
+    async fn create_with_free_slug(
+        state: &Arc<AllRooms>,
+        create_room_form: &CreateRoomForm,
+        mut slug: String,
+        settings: RoomSettings,
+    ) -> (String, Option<String>) {
+        for _ in 0..MAX_SLUG_ATTEMPTS {
+            if let Some(creation_token) = state.create_room(&slug, create_room_form.hours, create_room_form.minutes, settings.clone()).await {
+                return (slug, Some(creation_token));
+            }
+            slug = match create_room_form.slug_style {
+                SlugStyle::Name => break,
+                SlugStyle::Words => random_word_slug(),
+                SlugStyle::Token => random_token_slug(),
+            };
+        }
+        (slug, None)
+    }
+
+    fn idle_timeout(minutes: u64) -> Option<Duration> {
+        Some(minutes)
+            .filter(|minutes| *minutes > 0)
+            .map(|minutes| Duration::from_secs(minutes.min(MAX_IDLE_MINUTES) * 60))
+    }
+
+    fn owner_cookie(room_id: &str, creation_token: &str) -> String {
+        format!("impermachat_owner={}; Path=/room/{}; HttpOnly; SameSite=Lax", creation_token, room_id)
+    }
+
     pub async fn create_room(
+        headers: HeaderMap,
+        State(state): State<Arc<AllRooms>>,
         Form(create_room_form): Form<CreateRoomForm>,
     ) -> impl IntoResponse {
-        let room_path = format!("/room/{}?hours={}&minutes={}", sanitize_room_name(&create_room_form.room_name), create_room_form.hours, create_room_form.minutes);
+        let cookie_token = get_cookie(&headers, CSRF_COOKIE).unwrap_or_default();
+        if cookie_token.is_empty() || cookie_token != create_room_form.csrf_token {
+            // a fresh pair, otherwise a missing cookie would fail every retry
+            let csrf_token = random_token(32);
+            return (
+                StatusCode::FORBIDDEN,
+                [(SET_COOKIE, csrf_cookie(&csrf_token))],
+                IndexTemplate{
+                    show_message: true,
+                    message: "Your session expired, try again".to_string(),
+                    csrf_token,
+                },
+            ).into_response()
+        }
 
-        if create_room_form.room_name.trim().is_empty() {
+        // the room's webhook secret only comes back in the API's response, so the form can't set a URL
+        if !create_room_form.webhook_url.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                IndexTemplate{
+                    show_message: true,
+                    message: "Room webhooks can only be set through the API".to_string(),
+                    csrf_token: create_room_form.csrf_token,
+                },
+            ).into_response()
+        }
+
+        let Some((slug, settings)) = room_slug_and_settings(&create_room_form) else {
             return IndexTemplate{
                 show_message: true,
                 message: "Enter a room name".to_string(),
+                csrf_token: create_room_form.csrf_token,
             }.into_response()
+        };
+
+        let (slug, creation_token) = create_with_free_slug(&state, &create_room_form, slug, settings).await;
+        let room_path = format!("/room/{}", slug);
+        match creation_token {
+            Some(creation_token) => (
+                [(SET_COOKIE, owner_cookie(&slug, &creation_token))],
+                Redirect::to(&room_path),
+            ).into_response(),
+            // someone already made this room, join it instead
+            None if create_room_form.slug_style == SlugStyle::Name => Redirect::to(&room_path).into_response(),
+            // never send someone into a stranger's room because the dice came up the same
+            None => IndexTemplate{
+                show_message: true,
+                message: "Couldn't find a free room address, try again".to_string(),
+                csrf_token: create_room_form.csrf_token,
+            }.into_response(),
         }
+    }
 
-        Redirect::to(&room_path).into_response()
+    // Json only takes application/json, which other sites can't send without a CORS preflight the server
+    // never answers, so there's no CSRF token here. Each address gets a few rooms a minute instead.
+    pub async fn create_room_api(
+        ConnectInfo(address): ConnectInfo<SocketAddr>,
+        State(state): State<Arc<AllRooms>>,
+        Json(create_room_form): Json<CreateRoomForm>,
+    ) -> impl IntoResponse {
+        if !state.allow_api_creation(address.ip()).await {
+            return (StatusCode::TOO_MANY_REQUESTS, "Too many rooms created, try again in a minute").into_response()
+        }
+        let Some((slug, settings)) = room_slug_and_settings(&create_room_form) else {
+            return (StatusCode::BAD_REQUEST, "Enter a room name").into_response()
+        };
+
+        let title = settings.title.clone();
+        let (slug, creation_token) = create_with_free_slug(&state, &create_room_form, slug, settings).await;
+        match creation_token {
+            Some(creation_token) => {
+                let webhook_secret = state.webhook_secret(&slug).await;
+                let post_token = state.room_post_token(&slug).await;
+                (
+                    StatusCode::CREATED,
+                    [(SET_COOKIE, owner_cookie(&slug, &creation_token))],
+                    Json(CreatedRoom {
+                        url: format!("/room/{}", slug),
+                        title: if title.is_empty() { slug.clone() } else { title },
+                        room_id: slug,
+                        creation_token,
+                        webhook_secret,
+                        post_token,
+                    }),
+                ).into_response()
+            },
+            None => (StatusCode::CONFLICT, "Room already exists").into_response(),
+        }
     }
 }
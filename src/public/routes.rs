@@ -1,16 +1,24 @@
+use std::sync::Arc;
 use axum::{
     Router,
     routing::{get, post},
 };
 
+use crate::rooms::handlers::AllRooms;
+
 use super::handlers::{
     get::index,
-    post::create_room,
+    post::{
+        create_room,
+        create_room_api,
+    },
 };
 
-pub fn public_router() -> Router<()> {
+pub fn public_router(rooms: Arc<AllRooms>) -> Router<()> {
     Router::new()
         .route("/", get(index))
         .route("/", post(create_room))
+        .route("/api/rooms", post(create_room_api))
+        .with_state(rooms)
 }
 
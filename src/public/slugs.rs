@@ -0,0 +1,58 @@
+use rand::seq::SliceRandom;
+
+use crate::rooms::utils::random_token;
+
+const TOKEN_LENGTH: usize = 22;
+
+const ADJECTIVES: &[&str] = &[
+    "amber", "azure", "bold", "brave", "brisk", "calm", "cedar", "chilly",
+    "clever", "cobalt", "coral", "cosmic", "crimson", "curious", "dapper", "dusty",
+    "eager", "electric", "emerald", "fancy", "fiery", "fluffy", "frosty", "gentle",
+    "gilded", "glossy", "golden", "grand", "hazel", "humble", "icy", "indigo",
+    "jolly", "keen", "lively", "lucky", "lunar", "magenta", "mellow", "misty",
+    "modest", "mossy", "nimble", "noble", "ochre", "olive", "pale", "peppy",
+    "plucky", "polar", "proud", "quiet", "rapid", "rosy", "rustic", "sandy",
+    "scarlet", "shiny", "silent", "silver", "sleepy", "smoky", "snowy", "solar",
+    "spicy", "steady", "stormy", "sunny", "swift", "tawny", "tidy", "tiny",
+    "twilight", "velvet", "violet", "vivid", "wandering", "warm", "wild", "windy",
+    "wise", "witty", "woolly", "zesty",
+];
+
+const ANIMALS: &[&str] = &[
+    "badger", "beaver", "bison", "bobcat", "camel", "cheetah", "condor", "cougar",
+    "coyote", "crane", "dingo", "dolphin", "donkey", "eagle", "falcon", "ferret",
+    "finch", "flamingo", "fox", "gazelle", "gecko", "gibbon", "goose", "gopher",
+    "heron", "hippo", "ibis", "iguana", "jackal", "jaguar", "koala", "lemur",
+    "leopard", "llama", "lobster", "lynx", "magpie", "marmot", "marten", "meerkat",
+    "mole", "moose", "narwhal", "newt", "ocelot", "octopus", "oriole", "otter",
+    "owl", "panda", "panther", "parrot", "pelican", "penguin", "pigeon", "puffin",
+    "python", "quail", "rabbit", "raccoon", "raven", "robin", "salmon", "seal",
+    "shark", "sloth", "sparrow", "squid", "stork", "swan", "tapir", "tiger",
+    "toucan", "turtle", "viper", "walrus", "weasel", "whale", "wombat", "yak",
+    "zebra",
+];
+
+const PLACES: &[&str] = &[
+    "anchor", "arch", "bay", "beacon", "bluff", "brook", "canyon", "cape",
+    "cavern", "cliff", "coast", "cove", "creek", "delta", "desert", "dune",
+    "field", "fjord", "forest", "garden", "geyser", "glacier", "glade", "grove",
+    "harbor", "haven", "heath", "hill", "hollow", "island", "jungle", "lagoon",
+    "lake", "ledge", "marsh", "meadow", "mesa", "mountain", "oasis", "ocean",
+    "orchard", "pass", "peak", "pier", "pine", "plain", "plateau", "pond",
+    "prairie", "quarry", "rapids", "reef", "ridge", "river", "shore", "spring",
+    "steppe", "stream", "summit", "swamp", "thicket", "tide", "trail", "tundra",
+    "valley", "vista", "volcano", "waterfall", "wharf", "willow", "woods",
+];
+
+pub fn random_token_slug() -> String {
+    random_token(TOKEN_LENGTH).to_lowercase()
+}
+
+pub fn random_word_slug() -> String {
+    let mut rng = rand::thread_rng();
+    [ADJECTIVES, ANIMALS, PLACES]
+        .iter()
+        .map(|words| *words.choose(&mut rng).expect("Word lists are never empty"))
+        .collect::<Vec<&str>>()
+        .join("-")
+}
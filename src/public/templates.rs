@@ -5,5 +5,6 @@ use askama::Template;
 pub struct IndexTemplate {
     pub show_message: bool,
     pub message: String,
+    pub csrf_token: String,
 }
 
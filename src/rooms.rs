@@ -3,3 +3,13 @@ pub mod templates;
 pub mod routes;
 pub mod utils;
 pub mod middleware;
+pub mod invites;
+pub mod mentions;
+pub mod polls;
+pub mod replay;
+pub mod export;
+pub mod bots;
+pub mod commands;
+pub mod webhooks;
+pub mod gateway;
+pub mod irc;
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use super::handlers::AllRooms;
+
+pub mod dice;
+pub mod standup;
+pub mod timer;
+
+/// Room activity handed to bots, in the order it happened.
+#[derive(Clone, Debug)]
+pub enum BotEvent {
+    Joined { name: String },
+    // not sent for hidden drafts
+    Typing { name: String, content: String },
+    Submitted { name: String, content: String },
+    Shutdown,
+}
+
+/// A helper living in a room, posting under its own reserved name.
+#[async_trait]
+pub trait Bot: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn on_event(&self, event: &BotEvent, ctx: &BotContext);
+}
+
+/// Lets a bot talk back to its room, clone it to post later from a spawned task.
+#[derive(Clone)]
+pub struct BotContext {
+    state: Arc<AllRooms>,
+    room_id: String,
+    name: &'static str,
+}
+
+impl BotContext {
+    /// Posts to the room, returns false once the room is gone.
+    pub async fn say(&self, content: impl Into<String>) -> bool {
+        let mut rooms = self.state.rooms.lock().await;
+        match rooms.get_mut(&self.room_id) {
+            Some(room) => {
+                room.post_bot_message(self.name, content.into());
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+// the keys rooms can ask for at creation
+pub fn build(key: &str) -> Option<Box<dyn Bot>> {
+    match key {
+        "dice" => Some(Box::new(dice::DiceBot)),
+        "timer" => Some(Box::new(timer::TimerBot::default())),
+        "standup" => Some(Box::new(standup::StandupBot::default())),
+        _ => None,
+    }
+}
+
+pub async fn run_bots(
+    state: Arc<AllRooms>,
+    room_id: String,
+    bots: Vec<Box<dyn Bot>>,
+    mut events: mpsc::UnboundedReceiver<BotEvent>,
+) {
+    // ends when the room, and with it the sender, is dropped
+    while let Some(event) = events.recv().await {
+        for bot in &bots {
+            let ctx = BotContext {
+                state: state.clone(),
+                room_id: room_id.clone(),
+                name: bot.name(),
+            };
+            bot.on_event(&event, &ctx).await;
+        }
+    }
+}
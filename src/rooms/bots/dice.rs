@@ -0,0 +1,127 @@
+use std::fmt;
+use async_trait::async_trait;
+use rand::Rng;
+
+use super::{
+    Bot,
+    BotContext,
+    BotEvent,
+};
+
+const MAX_DICE: u32 = 100;
+const MAX_SIDES: u32 = 1000;
+const MAX_MODIFIER: i64 = 1000;
+
+#[derive(Clone, Debug)]
+pub struct Roll {
+    pub spec: String,
+    pub rolls: Vec<u32>,
+    pub modifier: i64,
+    pub total: i64,
+}
+
+impl fmt::Display for Roll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rolls = self.rolls.iter()
+            .map(u32::to_string)
+            .collect::<Vec<String>>()
+            .join(" + ");
+        match self.modifier {
+            0 if self.rolls.len() == 1 => write!(f, "{}: {}", self.spec, self.total),
+            0 => write!(f, "{}: {} = {}", self.spec, rolls, self.total),
+            modifier => write!(f, "{}: {} {} {} = {}", self.spec, rolls, if modifier < 0 { '-' } else { '+' }, modifier.abs(), self.total),
+        }
+    }
+}
+
+/// Rolls dice written like `2d6`, `d20` or `3d8+2`.
+pub fn roll(spec: &str) -> Option<Roll> {
+    let spec = spec.trim().to_lowercase();
+    let (count, rest) = spec.split_once('d')?;
+    let count = if count.is_empty() { 1 } else { count.parse().ok()? };
+
+    let (sides, modifier) = match rest.find(['+', '-']) {
+        Some(index) => (&rest[..index], rest[index..].parse::<i64>().ok()?),
+        None => (rest, 0),
+    };
+    let sides: u32 = sides.parse().ok()?;
+    if !(1..=MAX_DICE).contains(&count)
+        || !(2..=MAX_SIDES).contains(&sides)
+        || !(-MAX_MODIFIER..=MAX_MODIFIER).contains(&modifier)
+    {
+        return None;
+    }
+
+    let mut rng = rand::thread_rng();
+    let rolls: Vec<u32> = (0..count).map(|_| rng.gen_range(1..=sides)).collect();
+    let total = rolls.iter().map(|roll| *roll as i64).sum::<i64>() + modifier;
+    Some(Roll {
+        spec,
+        rolls,
+        modifier,
+        total,
+    })
+}
+
+/// Answers `!roll 2d6` with the result.
+pub struct DiceBot;
+
+#[async_trait]
+impl Bot for DiceBot {
+    fn name(&self) -> &'static str {
+        "dicebot"
+    }
+
+    async fn on_event(&self, event: &BotEvent, ctx: &BotContext) {
+        let BotEvent::Submitted { name, content } = event else {
+            return;
+        };
+        let Some(spec) = content.trim().strip_prefix("!roll") else {
+            return;
+        };
+
+        let spec = if spec.trim().is_empty() { "d6" } else { spec };
+        let reply = match roll(spec) {
+            Some(roll) => format!("{} rolled {}", name, roll),
+            None => format!("Try something like !roll 2d6 (up to {}d{})", MAX_DICE, MAX_SIDES),
+        };
+        ctx.say(reply).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolls_within_range() {
+        let two = roll("2d6").unwrap();
+        assert_eq!(two.rolls.len(), 2);
+        assert!((2..=12).contains(&two.total));
+
+        let one = roll(" D20 ").unwrap();
+        assert_eq!(one.spec, "d20");
+        assert_eq!(one.rolls.len(), 1);
+        assert!((1..=20).contains(&one.total));
+    }
+
+    #[test]
+    fn applies_the_modifier() {
+        let up = roll("3d8+2").unwrap();
+        assert_eq!(up.modifier, 2);
+        assert_eq!(up.total, up.rolls.iter().map(|roll| *roll as i64).sum::<i64>() + 2);
+
+        let down = roll("d4-10").unwrap();
+        assert_eq!(down.modifier, -10);
+        assert!(down.total < 0);
+        assert!(down.to_string().ends_with(&format!("- 10 = {}", down.total)));
+    }
+
+    #[test]
+    fn rejects_out_of_range_specs() {
+        for spec in ["", "d", "2x6", "0d6", "101d6", "d1", "d1001", "d6+1001", "d6-1001", "d6+-1", "d6-9223372036854775808", "d6+9223372036854775807"] {
+            assert!(roll(spec).is_none(), "{} should be refused", spec);
+        }
+        assert!(roll("100d1000+1000").is_some());
+    }
+}
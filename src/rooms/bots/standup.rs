@@ -0,0 +1,62 @@
+use std::{
+    collections::HashSet,
+    sync::Arc,
+};
+use async_trait::async_trait;
+use tokio::{
+    sync::Mutex,
+    time::{
+        Duration,
+        sleep,
+    },
+};
+
+use super::{
+    Bot,
+    BotContext,
+    BotEvent,
+};
+
+const NUDGE_AFTER: Duration = Duration::from_secs(2 * 60);
+
+/// Asks everyone who joins for their update, and nudges them once if they stay quiet.
+#[derive(Default)]
+pub struct StandupBot {
+    // joined but haven't started typing yet
+    quiet: Arc<Mutex<HashSet<String>>>,
+}
+
+#[async_trait]
+impl Bot for StandupBot {
+    fn name(&self) -> &'static str {
+        "standupbot"
+    }
+
+    async fn on_event(&self, event: &BotEvent, ctx: &BotContext) {
+        match event {
+            BotEvent::Joined { name } => {
+                self.quiet.lock().await.insert(name.clone());
+                ctx.say(format!("Welcome {}! What did you get done, what's next, and is anything blocking you?", name)).await;
+
+                let ctx = ctx.clone();
+                let quiet = self.quiet.clone();
+                let name = name.clone();
+                tokio::spawn(async move {
+                    sleep(NUDGE_AFTER).await;
+                    let still_quiet = quiet.lock().await.remove(&name);
+                    if still_quiet {
+                        ctx.say(format!("{}, whenever you're ready: done, next, blockers?", name)).await;
+                    }
+                });
+            },
+            BotEvent::Typing { name, content } if !content.is_empty() => {
+                self.quiet.lock().await.remove(name);
+            },
+            BotEvent::Submitted { name, .. } => {
+                self.quiet.lock().await.remove(name);
+            },
+            BotEvent::Shutdown => self.quiet.lock().await.clear(),
+            _ => {},
+        }
+    }
+}
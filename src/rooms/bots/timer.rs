@@ -0,0 +1,72 @@
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::time::{
+    Duration,
+    sleep,
+};
+
+use super::{
+    Bot,
+    BotContext,
+    BotEvent,
+};
+use crate::rooms::utils::{
+    format_duration,
+    parse_duration,
+};
+
+const MAX_TIMER: Duration = Duration::from_secs(60 * 60);
+const MAX_RUNNING_TIMERS: usize = 5;
+
+/// Answers `!timer 5m standup` and calls time when it's up.
+#[derive(Default)]
+pub struct TimerBot {
+    running: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl Bot for TimerBot {
+    fn name(&self) -> &'static str {
+        "timerbot"
+    }
+
+    async fn on_event(&self, event: &BotEvent, ctx: &BotContext) {
+        let BotEvent::Submitted { name, content } = event else {
+            return;
+        };
+        let Some(args) = content.trim().strip_prefix("!timer") else {
+            return;
+        };
+
+        let mut args = args.trim().splitn(2, char::is_whitespace);
+        let Some(duration) = args.next().and_then(parse_duration).filter(|d| *d <= MAX_TIMER) else {
+            ctx.say("Try something like !timer 5m standup (up to an hour)").await;
+            return;
+        };
+        let label = args.next().map(str::trim).filter(|label| !label.is_empty()).map(str::to_string);
+
+        if self.running.fetch_add(1, Ordering::SeqCst) >= MAX_RUNNING_TIMERS {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            ctx.say(format!("Only {} timers can run at once", MAX_RUNNING_TIMERS)).await;
+            return;
+        }
+
+        ctx.say(format!("{} set a timer for {}", name, format_duration(duration))).await;
+
+        let ctx = ctx.clone();
+        let running = self.running.clone();
+        let name = name.clone();
+        tokio::spawn(async move {
+            sleep(duration).await;
+            match label {
+                Some(label) => ctx.say(format!("Time's up, {}: {}", name, label)).await,
+                None => ctx.say(format!("Time's up, {}!", name)).await,
+            };
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
@@ -0,0 +1,215 @@
+use tokio::time::Duration;
+
+use super::bots::dice;
+use super::handlers::{
+    same_name,
+    Room,
+};
+use super::utils::{
+    format_duration,
+    parse_duration,
+};
+
+// same ceiling as the owner panel's extend control
+const MAX_EXTEND: Duration = Duration::from_secs(60 * 60);
+
+/// Who ran a command and the room it runs against, the room is already locked.
+pub struct CommandContext<'a> {
+    pub room: &'a mut Room,
+    pub connection_id: &'a str,
+    pub name: &'a str,
+    pub is_owner: bool,
+}
+
+pub enum Outcome {
+    // everyone sees the result in the chat log
+    Posted,
+    // only whoever ran the command sees these lines
+    Private(Vec<String>),
+}
+
+fn private(line: impl Into<String>) -> Outcome {
+    Outcome::Private(vec![line.into()])
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub summary: &'static str,
+    pub owner_only: bool,
+    run: fn(&mut CommandContext, &str) -> Outcome,
+}
+
+// add new commands here, /help lists them in this order
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "me",
+        usage: "/me waves",
+        summary: "Say what you're doing",
+        owner_only: false,
+        run: me,
+    },
+    Command {
+        name: "nick",
+        usage: "/nick new-name",
+        summary: "Change your name",
+        owner_only: false,
+        run: nick,
+    },
+    Command {
+        name: "topic",
+        usage: "/topic what we're talking about",
+        summary: "Set the room topic",
+        owner_only: true,
+        run: topic,
+    },
+    Command {
+        name: "roll",
+        usage: "/roll 2d6",
+        summary: "Roll dice for everyone to see",
+        owner_only: false,
+        run: roll,
+    },
+    Command {
+        name: "poll",
+        usage: "/poll Lunch? | Pizza | Tacos",
+        summary: "Start a poll, options separated by |",
+        owner_only: false,
+        run: poll,
+    },
+    Command {
+        name: "vote",
+        usage: "/vote 2",
+        summary: "Vote for an option in the newest open poll",
+        owner_only: false,
+        run: vote,
+    },
+    Command {
+        name: "extend",
+        usage: "/extend 10m",
+        summary: "Give the room more time",
+        owner_only: true,
+        run: extend,
+    },
+    Command {
+        name: "kick",
+        usage: "/kick name",
+        summary: "Remove someone from the room",
+        owner_only: true,
+        run: kick,
+    },
+    Command {
+        name: "help",
+        usage: "/help",
+        summary: "List commands",
+        owner_only: false,
+        run: help,
+    },
+];
+
+/// Runs the command in `input`, or returns None for ordinary messages (including `//escaped` ones).
+pub fn dispatch(ctx: &mut CommandContext, input: &str) -> Option<Outcome> {
+    let input = input.trim();
+    let command_line = input.strip_prefix('/').filter(|rest| !rest.starts_with('/'))?;
+    let (name, args) = command_line.split_once(char::is_whitespace).unwrap_or((command_line, ""));
+
+    let outcome = match COMMANDS.iter().find(|command| command.name.eq_ignore_ascii_case(name)) {
+        Some(command) if command.owner_only && !ctx.is_owner => {
+            private(format!("Only the room's owner can use /{}", command.name))
+        },
+        Some(command) => (command.run)(ctx, args.trim()),
+        None => private(format!("There's no /{} command, try /help", name)),
+    };
+    Some(outcome)
+}
+
+fn me(ctx: &mut CommandContext, args: &str) -> Outcome {
+    if args.is_empty() {
+        return private("Usage: /me waves");
+    }
+    ctx.room.post_emote(ctx.name, ctx.connection_id, args.to_string());
+    Outcome::Posted
+}
+
+fn nick(ctx: &mut CommandContext, args: &str) -> Outcome {
+    match ctx.room.rename(ctx.connection_id, args) {
+        Ok(()) => {
+            ctx.room.post_system_message(format!("{} is now known as {}", ctx.name, args));
+            Outcome::Posted
+        },
+        Err(reason) => private(reason),
+    }
+}
+
+fn topic(ctx: &mut CommandContext, args: &str) -> Outcome {
+    ctx.room.set_topic(args);
+    match args {
+        "" => ctx.room.post_system_message(format!("{} cleared the topic", ctx.name)),
+        topic => ctx.room.post_system_message(format!("{} set the topic: {}", ctx.name, topic)),
+    }
+    Outcome::Posted
+}
+
+fn roll(ctx: &mut CommandContext, args: &str) -> Outcome {
+    match dice::roll(if args.is_empty() { "d6" } else { args }) {
+        Some(roll) => {
+            ctx.room.post_system_message(format!("{} rolled {}", ctx.name, roll));
+            Outcome::Posted
+        },
+        None => private("Usage: /roll 2d6, /roll d20 or /roll 3d8+2"),
+    }
+}
+
+fn poll(ctx: &mut CommandContext, args: &str) -> Outcome {
+    let mut parts = args.split('|');
+    let question = parts.next().unwrap_or_default();
+    let options: Vec<&str> = parts.collect();
+    match ctx.room.start_poll(ctx.connection_id, ctx.name, question, &options) {
+        Ok(()) => Outcome::Posted,
+        Err(reason) => private(reason),
+    }
+}
+
+fn vote(ctx: &mut CommandContext, args: &str) -> Outcome {
+    let Some(option) = args.parse().ok() else {
+        return private("Usage: /vote 2 (the option's number)");
+    };
+    let Some(poll_id) = ctx.room.latest_poll() else {
+        return private("There's no open poll");
+    };
+    match ctx.room.vote(poll_id, ctx.connection_id, option) {
+        Ok(()) => private(format!("You voted for option {}", option)),
+        Err(reason) => private(reason),
+    }
+}
+
+fn extend(ctx: &mut CommandContext, args: &str) -> Outcome {
+    let Some(duration) = parse_duration(args).filter(|duration| *duration <= MAX_EXTEND) else {
+        return private("Usage: /extend 10m (up to an hour at a time)");
+    };
+    ctx.room.extend(duration);
+    ctx.room.post_system_message(format!("The room was extended by {}", format_duration(duration)));
+    Outcome::Posted
+}
+
+fn kick(ctx: &mut CommandContext, args: &str) -> Outcome {
+    if same_name(args, ctx.name) {
+        return private("You can't kick yourself");
+    }
+    match ctx.room.kick(args) {
+        Some(name) => {
+            ctx.room.post_system_message(format!("{} was removed from the room", name));
+            Outcome::Posted
+        },
+        None => private(format!("Nobody here is called {}", args)),
+    }
+}
+
+fn help(ctx: &mut CommandContext, _args: &str) -> Outcome {
+    let mut lines: Vec<String> = COMMANDS.iter()
+        .filter(|command| !command.owner_only || ctx.is_owner)
+        .map(|command| format!("{} - {}", command.usage, command.summary))
+        .collect();
+    lines.push("Start a message with // to send it with a single leading slash".to_string());
+    Outcome::Private(lines)
+}
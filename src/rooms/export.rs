@@ -0,0 +1,156 @@
+use askama::Template;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use super::handlers::{
+    Message,
+    MessageKind,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub enum ExportFormat {
+    Text,
+    Markdown,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "txt" => Some(Self::Text),
+            "md" => Some(Self::Markdown),
+            "json" => Some(Self::Json),
+            "html" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Markdown => "md",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Text => "text/plain; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Json => "application/json",
+            Self::Html => "text/html; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TranscriptEntry {
+    pub id: u64,
+    // chat, bot, webhook, emote or system, the last two are laid out like the room page does
+    pub kind: &'static str,
+    pub name: String,
+    pub content: String,
+    // unix seconds
+    pub sent_at: i64,
+    #[serde(skip)]
+    pub time: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Transcript {
+    // left out of spectator exports, the room id is enough to take a seat
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room_id: Option<String>,
+    pub title: String,
+    pub exported_at: i64,
+    pub messages: Vec<TranscriptEntry>,
+}
+
+#[derive(Template)]
+#[template(path = "transcript.txt")]
+struct TextTranscriptTemplate<'a> {
+    transcript: &'a Transcript,
+    exported: String,
+}
+
+#[derive(Template)]
+#[template(path = "transcript.md")]
+struct MarkdownTranscriptTemplate<'a> {
+    transcript: &'a Transcript,
+    exported: String,
+}
+
+#[derive(Template)]
+#[template(path = "transcript.html")]
+struct HtmlTranscriptTemplate<'a> {
+    transcript: &'a Transcript,
+    exported: String,
+}
+
+// times in transcripts are UTC, the server has no idea where anyone is
+fn format_utc(timestamp: OffsetDateTime) -> String {
+    format!(
+        "{} {:02}:{:02}:{:02} UTC",
+        timestamp.date(),
+        timestamp.hour(),
+        timestamp.minute(),
+        timestamp.second(),
+    )
+}
+
+fn kind_name(kind: MessageKind) -> &'static str {
+    match kind {
+        MessageKind::Chat => "chat",
+        MessageKind::Bot => "bot",
+        MessageKind::Emote => "emote",
+        MessageKind::System => "system",
+        MessageKind::Webhook => "webhook",
+    }
+}
+
+impl Transcript {
+    pub fn new(room_id: Option<&str>, title: &str, messages: &[Message]) -> Self {
+        Self {
+            room_id: room_id.map(str::to_string),
+            title: title.to_string(),
+            exported_at: OffsetDateTime::now_utc().unix_timestamp(),
+            messages: messages.iter()
+                .map(|message| TranscriptEntry {
+                    id: message.id,
+                    kind: kind_name(message.kind),
+                    name: message.name.clone(),
+                    content: message.content.clone(),
+                    sent_at: message.sent_at.unix_timestamp(),
+                    time: format_utc(message.sent_at),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn render(&self, format: ExportFormat) -> String {
+        let exported = OffsetDateTime::from_unix_timestamp(self.exported_at)
+            .map(format_utc)
+            .unwrap_or_default();
+
+        match format {
+            ExportFormat::Text => TextTranscriptTemplate { transcript: self, exported }.render().unwrap(),
+            ExportFormat::Markdown => {
+                // a bare newline would join lines back up, trailing double spaces keep them apart
+                let transcript = Transcript {
+                    messages: self.messages.iter()
+                        .map(|entry| TranscriptEntry {
+                            content: entry.content.replace('\n', "  \n"),
+                            ..entry.clone()
+                        })
+                        .collect(),
+                    ..self.clone()
+                };
+                MarkdownTranscriptTemplate { transcript: &transcript, exported }.render().unwrap()
+            },
+            ExportFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+            ExportFormat::Html => HtmlTranscriptTemplate { transcript: self, exported }.render().unwrap(),
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Seats for clients that don't speak SSE, like the IRC gateway.
+//!
+//! A gateway connection joins rooms by name like the browser's name prompt does, then turns the
+//! room's broadcast events into [`GatewayEvent`]s it can relay in its own protocol.
+
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::handlers::{
+    Action,
+    ActionEvent,
+    AllRooms,
+    Message,
+    MessageKind,
+    Room,
+    MAX_MESSAGE_SIZE,
+};
+use super::utils::format_remaining;
+
+// how much of the chat log a newly joined client gets played back
+const HISTORY_LENGTH: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JoinRefused {
+    NotFound,
+    Encrypted,
+    InviteOnly,
+    NameTaken,
+    Full,
+    Kicked,
+}
+
+impl JoinRefused {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::NotFound => "No such room, it may have expired",
+            Self::Encrypted => "Encrypted rooms can only be joined from a browser",
+            Self::InviteOnly => "Invite only rooms can only be joined from a browser",
+            Self::NameTaken => "Someone in the room already has that name",
+            Self::Full => "The room is full",
+            Self::Kicked => "You were removed from this room",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TypingState {
+    Active,
+    Paused,
+    Done,
+}
+
+#[derive(Clone, Debug)]
+pub enum GatewayEvent {
+    // chat log entries from everyone but the seat itself
+    Message { name: String, content: String, kind: MessageKind },
+    Typing { name: String, state: TypingState },
+    Joined(String),
+    Left(String),
+    Renamed { from: String, to: String },
+    Topic(Option<String>),
+    // command replies and room notices meant for this seat only
+    Notice(Vec<String>),
+    Kicked,
+    Closed(&'static str),
+}
+
+/// A name held in one room, along with everything the room says from now on.
+pub struct GatewaySeat {
+    pub room_id: String,
+    pub connection_id: String,
+    pub topic: Option<String>,
+    pub names: Vec<String>,
+    pub history: Vec<Message>,
+    rx: broadcast::Receiver<ActionEvent>,
+    last_message_id: u64,
+    members: HashMap<String, String>,
+    typing: HashMap<String, TypingState>,
+    pending: VecDeque<GatewayEvent>,
+}
+
+impl AllRooms {
+    /// Seats `name` in the room under the gateway's own connection id.
+    pub async fn gateway_join(&self, room_id: &str, connection_id: &str, name: &str) -> Result<GatewaySeat, JoinRefused> {
+        let mut rooms = self.rooms.lock().await;
+        let room = rooms.get_mut(room_id).ok_or(JoinRefused::NotFound)?;
+        if room.is_encrypted() {
+            return Err(JoinRefused::Encrypted);
+        }
+        if room.is_invite_only() {
+            return Err(JoinRefused::InviteOnly);
+        }
+        // kicked seats stay kicked, same as the browser's name prompt
+        if room.is_spectator(connection_id) {
+            return Err(JoinRefused::Kicked);
+        }
+        if room.name_taken(name) {
+            return Err(JoinRefused::NameTaken);
+        }
+        if room.is_full() {
+            return Err(JoinRefused::Full);
+        }
+
+        let rx = room.connect(connection_id);
+        room.seat_participant(connection_id, name);
+
+        let names = room.present_names();
+        let history = room.messages().iter()
+            .rev()
+            .take(HISTORY_LENGTH)
+            .rev()
+            .cloned()
+            .collect();
+
+        Ok(GatewaySeat {
+            room_id: room_id.to_string(),
+            connection_id: connection_id.to_string(),
+            topic: room.topic().map(str::to_string),
+            names,
+            history,
+            rx,
+            last_message_id: room.last_message_id(),
+            members: room.seated().clone(),
+            typing: HashMap::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Gives up the seat right away, there are no other tabs to wait for.
+    pub async fn gateway_leave(&self, room_id: &str, connection_id: &str) {
+        let mut rooms = self.rooms.lock().await;
+        if let Some(room) = rooms.get_mut(room_id) {
+            room.disconnect(connection_id);
+        }
+    }
+
+    /// Handled exactly like the browser's submit, slash commands included.
+    pub async fn gateway_say(&self, room_id: &str, connection_id: &str, text: &str) {
+        let mut rooms = self.rooms.lock().await;
+        let Some(room) = rooms.get_mut(room_id) else {
+            return;
+        };
+        let Some(name) = room.name_of(connection_id).map(str::to_string) else {
+            return;
+        };
+        let text = if text.len() > MAX_MESSAGE_SIZE {
+            "This message was too long! Keep it under 4,000 characters".to_string()
+        } else {
+            text.to_string()
+        };
+        room.accept_message(connection_id, name, text, false);
+    }
+
+    pub async fn gateway_emote(&self, room_id: &str, connection_id: &str, text: &str) {
+        let mut rooms = self.rooms.lock().await;
+        let Some(room) = rooms.get_mut(room_id) else {
+            return;
+        };
+        if let Some(name) = room.name_of(connection_id).map(str::to_string) {
+            room.post_emote(&name, connection_id, text.chars().take(MAX_MESSAGE_SIZE).collect());
+        }
+    }
+
+    pub async fn room_topic(&self, room_id: &str) -> Option<String> {
+        self.rooms.lock().await.get(room_id)?.topic().map(str::to_string)
+    }
+
+    pub async fn room_names(&self, room_id: &str) -> Vec<String> {
+        self.rooms.lock().await.get(room_id).map(Room::present_names).unwrap_or_default()
+    }
+
+    pub async fn gateway_rename(&self, room_id: &str, connection_id: &str, name: &str) -> Result<(), &'static str> {
+        let mut rooms = self.rooms.lock().await;
+        let room = rooms.get_mut(room_id).ok_or(JoinRefused::NotFound.message())?;
+        let old_name = room.name_of(connection_id).unwrap_or_default().to_string();
+        room.rename(connection_id, name)?;
+        room.post_system_message(format!("{} is now known as {}", old_name, name));
+        Ok(())
+    }
+}
+
+impl GatewaySeat {
+    /// Waits for the next thing worth telling the client about.
+    pub async fn next(&mut self, state: &AllRooms) -> GatewayEvent {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return event;
+            }
+
+            let event = match self.rx.recv().await {
+                Ok(event) => event,
+                // missed events only matter for what we diff, which is re-read below
+                Err(RecvError::Lagged(_)) => ActionEvent {
+                    connection_id: "System".to_string(),
+                    action: Action::Send,
+                },
+                Err(RecvError::Closed) => return GatewayEvent::Closed("The room closed."),
+            };
+
+            let rooms = state.rooms.lock().await;
+            let Some(room) = rooms.get(&self.room_id) else {
+                return GatewayEvent::Closed("The room closed.");
+            };
+            self.translate(room, event);
+        }
+    }
+
+    fn translate(&mut self, room: &Room, event: ActionEvent) {
+        let for_me = event.connection_id == self.connection_id;
+        match event.action {
+            Action::Send => self.new_messages(room),
+            Action::TypingFrame(typists) => {
+                for name in typists {
+                    self.typing_changed(room, &name);
+                }
+            },
+            Action::TopicChanged => self.pending.push_back(GatewayEvent::Topic(room.topic().map(str::to_string))),
+            Action::Notice(lines) if for_me => self.pending.push_back(GatewayEvent::Notice(lines)),
+            Action::Kicked if for_me => self.pending.push_back(GatewayEvent::Kicked),
+            Action::ShutdownRoom(reason) => self.pending.push_back(GatewayEvent::Closed(reason.message())),
+            Action::ExpiryWarning(remaining) => self.pending.push_back(GatewayEvent::Notice(vec![
+                format!("This room closes in {}", format_remaining(remaining)),
+            ])),
+            Action::FinalCountdown(remaining) => self.pending.push_back(GatewayEvent::Notice(vec![
+                format!("Time's up, the room closes in {} unless the owner extends it", format_remaining(remaining)),
+            ])),
+            Action::Extended => self.pending.push_back(GatewayEvent::Notice(vec![
+                "The owner gave the room more time".to_string(),
+            ])),
+            _ => {},
+        }
+        self.membership_changed(room);
+    }
+
+    fn new_messages(&mut self, room: &Room) {
+        let seen = self.last_message_id;
+        for message in room.messages().iter().filter(|message| message.id > seen) {
+            self.last_message_id = message.id;
+            if message.connection_id == self.connection_id {
+                continue;
+            }
+            // a sent message ends that person's typing
+            self.typing.remove(&message.name);
+            self.pending.push_back(GatewayEvent::Message {
+                name: message.name.clone(),
+                content: message.content.clone(),
+                kind: message.kind,
+            });
+        }
+    }
+
+    fn typing_changed(&mut self, room: &Room, name: &str) {
+        let Some(draft) = room.draft(name) else {
+            return;
+        };
+        if draft.connection_id == self.connection_id {
+            return;
+        }
+        let state = if draft.content.is_empty() {
+            TypingState::Done
+        } else if draft.idle {
+            TypingState::Paused
+        } else {
+            TypingState::Active
+        };
+        let previous = self.typing.insert(name.to_string(), state).unwrap_or(TypingState::Done);
+        if previous != state {
+            self.pending.push_back(GatewayEvent::Typing { name: name.to_string(), state });
+        }
+    }
+
+    // joins, leaves and renames aren't events of their own, so compare who's seated
+    fn membership_changed(&mut self, room: &Room) {
+        if *room.seated() == self.members {
+            return;
+        }
+        for (connection_id, name) in room.seated() {
+            if connection_id == &self.connection_id {
+                continue;
+            }
+            match self.members.get(connection_id) {
+                None => self.pending.push_back(GatewayEvent::Joined(name.clone())),
+                Some(old_name) if old_name != name => self.pending.push_back(GatewayEvent::Renamed {
+                    from: old_name.clone(),
+                    to: name.clone(),
+                }),
+                Some(_) => {},
+            }
+        }
+        for (connection_id, name) in &self.members {
+            if connection_id != &self.connection_id && !room.seated().contains_key(connection_id) {
+                self.typing.remove(name);
+                self.pending.push_back(GatewayEvent::Left(name.clone()));
+            }
+        }
+        self.members = room.seated().clone();
+    }
+}
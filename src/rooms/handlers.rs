@@ -1,17 +1,25 @@
 use std::{
     sync::Arc,
     convert::Infallible,
+    net::IpAddr,
     time::Instant,
-    collections::HashMap,
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
 };
 use askama::Template;
 use tokio::{
     sync::{
         Mutex,
         broadcast,
+        mpsc,
     },
     time::{
         Duration,
+        MissedTickBehavior,
+        interval,
         sleep,
     },
 };
@@ -34,12 +42,18 @@ use axum::{
     http::{
         StatusCode,
         HeaderMap,
+        header::SET_COOKIE,
     },
     body::Body,
 };
 use futures_util::stream::Stream;
-use serde::Deserialize;
+use uuid::Uuid;
+use serde::{
+    Deserialize,
+    Deserializer,
+};
 use async_stream::try_stream;
+use time::OffsetDateTime;
 use tokio_stream::{
     wrappers::BroadcastStream,
     StreamExt as _,
@@ -49,9 +63,51 @@ use super::utils::{
     create_fragments_event,
     name_to_color,
     format_time,
+    format_remaining,
     get_connection_cookie,
+    get_cookie,
+    create_fragments_response,
+    sign,
+    verify_signature,
 };
 
+use super::invites::Invites;
+use super::mentions::{
+    self,
+    Mention,
+    Part,
+};
+use super::polls::{
+    Polls,
+    PollView,
+};
+use super::commands::{
+    self,
+    CommandContext,
+    Outcome,
+};
+use super::bots::{
+    self,
+    Bot,
+    BotEvent,
+};
+use super::export::{
+    ExportFormat,
+    Transcript,
+};
+use super::replay::{
+    Snapshot,
+    TypingRecorder,
+};
+use super::webhooks::{
+    self,
+    RoomWebhooks,
+    WebhookEvent,
+    WebhookTarget,
+    Webhooks,
+};
+use crate::config::Config;
+
 use super::templates::{
     RoomTemplate,
     SubmitTemplate,
@@ -59,26 +115,104 @@ use super::templates::{
     ShutdownTemplate,
     InitNameTemplate,
     TypingTemplate,
+    TypingBoxTemplate,
     MajorErrorTemplate,
     ChatInputTemplate,
+    WaitingTemplate,
+    SpectatorTemplate,
+    RoomNotFoundTemplate,
+    InviteRequiredTemplate,
+    InviteListTemplate,
+    ReplayTemplate,
+    ExpiryWarningTemplate,
+    NoticeTemplate,
+    TopicTemplate,
+    PinnedTemplate,
+    PollsTemplate,
+    SearchResultsTemplate,
 };
 
-const MAX_MESSAGE_SIZE: usize = 4000;
+pub const MAX_MESSAGE_SIZE: usize = 4000;
+// base64 AES-GCM output for a 4000 character message with multi-byte characters
+const MAX_ENCRYPTED_MESSAGE_SIZE: usize = 24000;
+const MAX_PARTICIPANTS: usize = 50;
+pub const MAX_TITLE_LENGTH: usize = 64;
+pub const MAX_NAME_LENGTH: usize = 32;
+const MAX_TOPIC_LENGTH: usize = 200;
+// stands in for a hidden draft, only whether something's being typed is kept
+const HIDDEN_DRAFT: &str = "…";
+// pinning another one unpins the oldest
+const MAX_PINS: usize = 3;
+// same ceiling as the creation form, 12 hours and 60 minutes
+const MAX_ROOM_LIFETIME: Duration = Duration::from_secs(13 * 60 * 60);
+// how long a participant can be gone before their seat is given away (covers page reloads)
+const SEAT_RELEASE_GRACE: Duration = Duration::from_secs(10);
+// IMPERMACHAT_API_ROOMS_PER_MINUTE counts over this window
+const API_CREATION_WINDOW: Duration = Duration::from_secs(60);
+// long pauses while composing get squashed so replays don't stall
+const MAX_REPLAY_PAUSE: Duration = Duration::from_secs(2);
+const MAX_SEARCH_RESULTS: usize = 50;
+// readable from the page too, alerts.js checks it when a message comes in
+const ALERTS_COOKIE: &str = "impermachat_alerts";
+const SEARCH_SNIPPET_LENGTH: usize = 160;
 
 #[derive(Clone, Debug)]
-enum Action {
+pub enum Action {
     Typing,
     Send,
     SetName,
-    ShutdownRoom,
+    ShutdownRoom(ShutdownReason),
     UpdateTime,
     MajorError,
+    WaitingLine,
+    // typists whose buffers changed since the last frame
+    TypingFrame(Vec<String>),
+    // seconds left when a warning threshold was crossed
+    ExpiryWarning(u64),
+    // time ran out, seconds of grace left for the owner to extend
+    FinalCountdown(u64),
+    Extended,
+    // command output meant only for the event's connection
+    Notice(Vec<String>),
+    Kicked,
+    TopicChanged,
+    PinsChanged,
+    PollsChanged,
+    // the event's connection was mentioned in this message
+    Mentioned(u64),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ShutdownReason {
+    Expired,
+    // nobody connected for the room's empty timeout
+    Empty,
+    // no messages for the room's quiet timeout
+    Quiet,
+}
+
+impl ShutdownReason {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::Expired => "expired",
+            Self::Empty => "empty",
+            Self::Quiet => "quiet",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Expired => "The room's time ran out.",
+            Self::Empty => "Everyone left, so the room closed.",
+            Self::Quiet => "Nobody sent a message for a while, so the room closed.",
+        }
+    }
 }
 
 #[derive(Clone)]
-struct ActionEvent {
-    connection_id: String,
-    action: Action,
+pub struct ActionEvent {
+    pub connection_id: String,
+    pub action: Action,
 }
 
 #[derive(Clone)]
@@ -91,14 +225,855 @@ pub struct Room {
     id_to_name: HashMap<String, String>,
     name_to_color: HashMap<String, String>,
     expiration: Instant,
+    max_participants: Option<usize>,
+    waiting_line: VecDeque<(String, String)>,
+    connections: HashMap<String, u32>,
+    spectator_key: String,
+    spectators: HashSet<String>,
+    title: String,
+    // mixed into the creation token so tokens don't carry over to a later room with the same slug
+    creation_nonce: String,
+    invite_only: bool,
+    invites: Invites,
+    // people who joined an invite only room with a valid invite
+    admitted: HashSet<String>,
+    // messages are ciphertext the server can't read, the key only lives in the URL fragment
+    encrypted: bool,
+    // last applied keystroke diff per typist, missing until they send a full buffer
+    typing_seqs: HashMap<String, u64>,
+    // typists waiting for the next typing frame
+    dirty_typists: HashSet<String>,
+    last_typed: HashMap<String, Instant>,
+    next_message_id: u64,
+    record_typing: bool,
+    // drafts show up as "X is typing…" instead of their content
+    hidden_typing: bool,
+    // connection ids of people who hid their own drafts
+    hidden_typists: HashSet<String>,
+    recorder: TypingRecorder,
+    exports_disabled: bool,
+    // smallest expiry warning already shown, so each threshold fires once
+    last_warning: Option<u64>,
+    // set once time runs out, the room closes when this passes unless extended
+    grace_until: Option<Instant>,
+    close_when_empty: Option<Duration>,
+    close_when_quiet: Option<Duration>,
+    empty_since: Option<Instant>,
+    last_message_at: Instant,
+    // reserved names, nobody else can take these
+    bot_names: Vec<&'static str>,
+    bot_tx: Option<mpsc::UnboundedSender<BotEvent>>,
+    topic: Option<String>,
+    // message ids, oldest pin first
+    pinned: Vec<u64>,
+    polls: Polls,
+    webhooks: Option<RoomWebhooks>,
+    // signs deliveries to the room's own webhook URL
+    webhook_secret: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RoomSettings {
+    pub max_participants: Option<usize>,
+    pub title: String,
+    pub invite_only: bool,
+    pub encrypted: bool,
+    pub record_typing: bool,
+    pub hidden_typing: bool,
+    pub exports_disabled: bool,
+    pub close_when_empty: Option<Duration>,
+    pub close_when_quiet: Option<Duration>,
+    // keys from bots::build
+    pub bots: Vec<String>,
+    // only honoured when the server allows room webhooks
+    pub webhook_url: String,
+    pub topic: String,
+}
+
+impl Room {
+    fn new(expiration: Instant, settings: RoomSettings) -> Self {
+        let (tx, _rx) = broadcast::channel(100);
+        Self {
+            tx,
+            message_history: Vec::new(),
+            join_count: 0,
+            name_to_id: HashMap::new(),
+            id_to_name: HashMap::new(),
+            name_to_color: HashMap::new(),
+            typing_state: HashMap::new(),
+            expiration,
+            max_participants: settings.max_participants,
+            waiting_line: VecDeque::new(),
+            connections: HashMap::new(),
+            spectator_key: Uuid::new_v4().simple().to_string(),
+            spectators: HashSet::new(),
+            title: settings.title,
+            creation_nonce: Uuid::new_v4().simple().to_string(),
+            invite_only: settings.invite_only,
+            invites: Invites::default(),
+            admitted: HashSet::new(),
+            encrypted: settings.encrypted,
+            typing_seqs: HashMap::new(),
+            dirty_typists: HashSet::new(),
+            last_typed: HashMap::new(),
+            next_message_id: 1,
+            record_typing: settings.record_typing,
+            hidden_typing: settings.hidden_typing,
+            hidden_typists: HashSet::new(),
+            recorder: TypingRecorder::default(),
+            exports_disabled: settings.exports_disabled,
+            last_warning: None,
+            grace_until: None,
+            close_when_empty: settings.close_when_empty,
+            close_when_quiet: settings.close_when_quiet,
+            empty_since: None,
+            last_message_at: Instant::now(),
+            bot_names: Vec::new(),
+            bot_tx: None,
+            topic: clean_topic(&settings.topic),
+            pinned: Vec::new(),
+            polls: Polls::default(),
+            webhooks: None,
+            webhook_secret: None,
+        }
+    }
+
+    // adds to the chat log and returns the new message's id
+    fn push_message(&mut self, name: &str, connection_id: &str, content: String, kind: MessageKind) -> u64 {
+        let id = self.next_message_id;
+        self.next_message_id += 1;
+
+        self.last_message_at = Instant::now();
+        let has_replay = self.record_typing && {
+            let (kept, evicted) = self.recorder.finish(name, id);
+            for message in self.message_history.iter_mut().filter(|message| evicted.contains(&message.id)) {
+                message.has_replay = false;
+            }
+            kept
+        };
+        let mentions = if self.encrypted || kind == MessageKind::System {
+            Vec::new()
+        } else {
+            mentions::find(&content, &self.name_to_id)
+        };
+        self.message_history.push(Message {
+            id,
+            name: name.to_string(),
+            content,
+            mentions,
+            color: name_to_color(name),
+            connection_id: connection_id.to_string(),
+            encrypted: self.encrypted,
+            idle: false,
+            has_replay,
+            hidden: false,
+            sent_at: OffsetDateTime::now_utc(),
+            kind,
+        });
+        id
+    }
+
+    pub fn post_bot_message(&mut self, name: &str, content: String) {
+        let id = self.push_message(name, "System", content, MessageKind::Bot);
+        if let Err(e) = self.tx.send(ActionEvent {
+            connection_id: "System".to_string(),
+            action: Action::Send,
+        }) {
+            println!("Error broadcasting bot message: {}", e);
+        }
+        self.notify_mentions(id);
+    }
+
+    // goes through the same bots and webhooks as a participant's message
+    fn post_incoming_message(&mut self, name: &str, content: String) {
+        self.notify_bots(BotEvent::Submitted {
+            name: name.to_string(),
+            content: content.clone(),
+        });
+        self.notify_webhooks(WebhookEvent::MessageSubmitted {
+            name: name.to_string(),
+            content: Some(content.clone()),
+        });
+        let id = self.push_message(name, "Webhook", content, MessageKind::Webhook);
+        if let Err(e) = self.tx.send(ActionEvent {
+            connection_id: "System".to_string(),
+            action: Action::Send,
+        }) {
+            println!("Error broadcasting incoming message: {}", e);
+        }
+        self.notify_mentions(id);
+    }
+
+    // runs commands, or posts to the chat log and tells the sender's input to clear
+    pub fn accept_message(&mut self, connection_id: &str, person_name: String, new_message: String, is_owner: bool) {
+        // commands can't be read out of ciphertext
+        let command = if self.encrypted {
+            None
+        } else {
+            commands::dispatch(&mut CommandContext {
+                room: self,
+                connection_id,
+                name: &person_name,
+                is_owner,
+            }, &new_message)
+        };
+
+        let mut posted = None;
+        match command {
+            Some(Outcome::Posted) => {},
+            Some(Outcome::Private(lines)) => {
+                let _ = self.tx.send(ActionEvent {
+                    connection_id: connection_id.to_string(),
+                    action: Action::Notice(lines),
+                });
+            },
+            None => {
+                // a leading // is how you say something that starts with a slash
+                let new_message = match new_message.trim_start().strip_prefix("//") {
+                    Some(rest) if !self.encrypted => format!("/{}", rest),
+                    _ => new_message,
+                };
+                if !self.encrypted {
+                    self.notify_bots(BotEvent::Submitted {
+                        name: person_name.clone(),
+                        content: new_message.clone(),
+                    });
+                }
+                self.notify_webhooks(WebhookEvent::MessageSubmitted {
+                    name: person_name.clone(),
+                    content: Some(new_message.clone()).filter(|_| !self.encrypted),
+                });
+                posted = Some(self.push_message(&person_name, connection_id, new_message, MessageKind::Chat));
+            },
+        }
+
+        // a rename moved the typing buffer, reset it under the current name
+        let person_name = self.id_to_name.get(connection_id).cloned().unwrap_or(person_name);
+        self.typing_state.insert(person_name.clone(), Message{
+            id: 0,
+            name: person_name.clone(),
+            content: String::from(""),
+            color: name_to_color(&person_name),
+            connection_id: connection_id.to_string(),
+            encrypted: self.encrypted,
+            idle: false,
+            has_replay: false,
+            hidden: self.drafts_hidden(connection_id),
+            sent_at: OffsetDateTime::now_utc(),
+            kind: MessageKind::Chat,
+            mentions: Vec::new(),
+        });
+        // the buffer was cleared underneath the client, make it resync
+        self.typing_seqs.remove(&person_name);
+        if let Err(e) = self.tx.send(ActionEvent {
+            connection_id: connection_id.to_string(),
+            action: Action::Send,
+        }) {
+            println!("Error broadcasting: {}", e);
+        }
+        if let Some(id) = posted {
+            self.notify_mentions(id);
+        }
+    }
+
+    pub fn post_system_message(&mut self, content: String) {
+        self.push_message("", "System", content, MessageKind::System);
+        let _ = self.tx.send(ActionEvent {
+            connection_id: "System".to_string(),
+            action: Action::Send,
+        });
+    }
+
+    pub fn post_emote(&mut self, name: &str, connection_id: &str, content: String) {
+        let id = self.push_message(name, connection_id, content, MessageKind::Emote);
+        let _ = self.tx.send(ActionEvent {
+            connection_id: "System".to_string(),
+            action: Action::Send,
+        });
+        self.notify_mentions(id);
+    }
+
+    // sent after the message itself so it's already on the page when the alert lands
+    fn notify_mentions(&self, message_id: u64) {
+        let Some(message) = self.message_history.iter().rev().find(|message| message.id == message_id) else {
+            return;
+        };
+        let mut notified = HashSet::new();
+        for mention in &message.mentions {
+            if mention.connection_id == message.connection_id || !notified.insert(&mention.connection_id) {
+                continue;
+            }
+            let _ = self.tx.send(ActionEvent {
+                connection_id: mention.connection_id.clone(),
+                action: Action::Mentioned(message_id),
+            });
+        }
+    }
+
+    pub fn topic(&self) -> Option<&str> {
+        self.topic.as_deref()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    pub fn is_invite_only(&self) -> bool {
+        self.invite_only
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.message_history
+    }
+
+    // 0 until the first message
+    pub fn last_message_id(&self) -> u64 {
+        self.next_message_id - 1
+    }
+
+    /// Connection id to name for everyone seated.
+    pub fn seated(&self) -> &HashMap<String, String> {
+        &self.id_to_name
+    }
+
+    pub fn name_of(&self, connection_id: &str) -> Option<&str> {
+        self.id_to_name.get(connection_id).map(String::as_str)
+    }
+
+    pub fn draft(&self, name: &str) -> Option<&Message> {
+        self.typing_state.get(name)
+    }
+
+    // everyone seated plus the bots, sorted for NAMES replies
+    pub fn present_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.name_to_id.keys().cloned().collect();
+        names.extend(self.bot_names.iter().map(|bot| bot.to_string()));
+        names.sort();
+        names
+    }
+
+    pub fn set_topic(&mut self, topic: &str) {
+        self.topic = clean_topic(topic);
+        let _ = self.tx.send(ActionEvent {
+            connection_id: "System".to_string(),
+            action: Action::TopicChanged,
+        });
+    }
+
+    // system lines have nothing worth pinning
+    fn pin(&mut self, message_id: u64) -> bool {
+        let pinnable = self.message_history.iter()
+            .any(|message| message.id == message_id && !message.is_system());
+        if !pinnable || self.pinned.contains(&message_id) {
+            return false;
+        }
+        self.pinned.push(message_id);
+        if self.pinned.len() > MAX_PINS {
+            self.pinned.remove(0);
+        }
+        self.pins_changed();
+        true
+    }
+
+    fn unpin(&mut self, message_id: u64) -> bool {
+        let pin_count = self.pinned.len();
+        self.pinned.retain(|id| *id != message_id);
+        if self.pinned.len() == pin_count {
+            return false;
+        }
+        self.pins_changed();
+        true
+    }
+
+    fn pins_changed(&self) {
+        if let Err(e) = self.tx.send(ActionEvent {
+            connection_id: "System".to_string(),
+            action: Action::PinsChanged,
+        }) {
+            println!("Error broadcasting pins: {}", e);
+        }
+    }
+
+    fn pinned_messages(&self) -> Vec<Message> {
+        self.pinned.iter()
+            .filter_map(|id| self.message_history.iter().find(|message| message.id == *id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn start_poll(&mut self, connection_id: &str, name: &str, question: &str, options: &[&str]) -> Result<(), &'static str> {
+        let poll = self.polls.create(name, connection_id, question, options)?;
+        let numbered: Vec<String> = poll.options.iter()
+            .enumerate()
+            .map(|(index, option)| format!("{}. {}", index + 1, option))
+            .collect();
+        let announcement = format!("{} started a poll: {} {}", name, poll.question, numbered.join("  "));
+        self.post_system_message(announcement);
+        self.polls_changed();
+        Ok(())
+    }
+
+    pub fn vote(&mut self, poll_id: u64, connection_id: &str, option: usize) -> Result<(), &'static str> {
+        self.polls.vote(poll_id, connection_id, option)?;
+        self.polls_changed();
+        Ok(())
+    }
+
+    pub fn latest_poll(&self) -> Option<u64> {
+        self.polls.latest_open()
+    }
+
+    pub fn close_poll(&mut self, poll_id: u64, connection_id: &str, is_owner: bool) -> Result<(), &'static str> {
+        let poll = self.polls.close(poll_id, connection_id, is_owner)?;
+        let announcement = format!("Poll closed: {} ({})", poll.question, poll.results());
+        self.post_system_message(announcement);
+        self.polls_changed();
+        Ok(())
+    }
+
+    // the final countdown started, so whatever's still open gets its final count
+    fn close_polls(&mut self) {
+        let closed = self.polls.close_all();
+        if closed.is_empty() {
+            return;
+        }
+        for poll in closed {
+            self.post_system_message(format!("Poll closed: {} ({})", poll.question, poll.results()));
+        }
+        self.polls_changed();
+    }
+
+    // the owner extended during the final countdown, so the polls it closed carry on
+    fn reopen_polls(&mut self) {
+        let reopened = self.polls.reopen_all();
+        if reopened.is_empty() {
+            return;
+        }
+        for poll in reopened {
+            self.post_system_message(format!("Poll reopened: {}", poll.question));
+        }
+        self.polls_changed();
+    }
+
+    fn polls_changed(&self) {
+        if let Err(e) = self.tx.send(ActionEvent {
+            connection_id: "System".to_string(),
+            action: Action::PollsChanged,
+        }) {
+            println!("Error broadcasting polls: {}", e);
+        }
+    }
+
+    // spectators and people who haven't picked a name only see the counts
+    fn poll_views(&self, connection_id: &str, is_owner: bool) -> Vec<PollView> {
+        let can_vote = self.id_to_name.contains_key(connection_id) && !self.is_spectator(connection_id);
+        self.polls.views(connection_id, is_owner, can_vote)
+    }
+
+    // moves everything keyed by the old name over to the new one
+    pub fn rename(&mut self, connection_id: &str, new_name: &str) -> Result<(), &'static str> {
+        let new_name = new_name.trim();
+        if new_name.is_empty() {
+            return Err("Usage: /nick new-name");
+        }
+        if new_name.chars().count() > MAX_NAME_LENGTH {
+            return Err("That name is too long");
+        }
+        let Some(old_name) = self.id_to_name.get(connection_id).cloned() else {
+            return Err("Pick a name first");
+        };
+        // changing only the case of your own name is fine
+        if self.name_taken(new_name) && !same_name(&old_name, new_name) {
+            return Err("That name is already taken");
+        }
+
+        self.name_to_id.remove(&old_name);
+        self.name_to_id.insert(new_name.to_string(), connection_id.to_string());
+        self.id_to_name.insert(connection_id.to_string(), new_name.to_string());
+        self.name_to_color.remove(&old_name);
+        self.name_to_color.insert(new_name.to_string(), name_to_color(new_name));
+        if let Some(mut draft) = self.typing_state.remove(&old_name) {
+            draft.name = new_name.to_string();
+            draft.color = name_to_color(new_name);
+            self.typing_state.insert(new_name.to_string(), draft);
+        }
+        if let Some(seq) = self.typing_seqs.remove(&old_name) {
+            self.typing_seqs.insert(new_name.to_string(), seq);
+        }
+        if let Some(typed) = self.last_typed.remove(&old_name) {
+            self.last_typed.insert(new_name.to_string(), typed);
+        }
+        self.dirty_typists.remove(&old_name);
+        self.recorder.discard(&old_name);
+
+        // re-renders their input and everyone's typing boxes
+        let _ = self.tx.send(ActionEvent {
+            connection_id: connection_id.to_string(),
+            action: Action::SetName,
+        });
+        Ok(())
+    }
+
+    // kicked people can keep watching but can't take a seat again, returns the name they had
+    pub fn kick(&mut self, name: &str) -> Option<String> {
+        let (name, connection_id) = self.name_to_id.iter()
+            .find(|(seated, _)| same_name(seated, name))
+            .map(|(seated, connection_id)| (seated.clone(), connection_id.clone()))?;
+        self.spectators.insert(connection_id.clone());
+        self.admitted.remove(&connection_id);
+        self.release_participant(&connection_id);
+        let _ = self.tx.send(ActionEvent {
+            connection_id,
+            action: Action::Kicked,
+        });
+        Some(name)
+    }
+
+    fn notify_bots(&self, event: BotEvent) {
+        if let Some(bot_tx) = &self.bot_tx {
+            let _ = bot_tx.send(event);
+        }
+    }
+
+    fn notify_webhooks(&self, event: WebhookEvent) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.send(event);
+        }
+    }
+
+    // the owner and anyone already admitted get in, everyone else needs a live invite
+    fn can_view(&self, connection_id: Option<&str>, is_owner: bool, invite: Option<&str>) -> bool {
+        !self.invite_only
+            || is_owner
+            || connection_id.is_some_and(|id| self.admitted.contains(id))
+            || invite.is_some_and(|invite| self.invites.is_valid(invite))
+    }
+
+    // opening the room stream uses up one join from the invite, after that the connection is admitted
+    fn admit(&mut self, connection_id: &str, is_owner: bool, invite: Option<&str>) -> bool {
+        if !self.invite_only || is_owner || self.admitted.contains(connection_id) {
+            return true;
+        }
+        if !invite.is_some_and(|invite| self.invites.redeem(invite)) {
+            return false;
+        }
+        self.admitted.insert(connection_id.to_string());
+        true
+    }
+
+    pub fn is_spectator(&self, connection_id: &str) -> bool {
+        self.spectators.contains(connection_id)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.max_participants
+            .is_some_and(|max| self.name_to_id.len() >= max)
+    }
+
+    pub fn name_taken(&self, name: &str) -> bool {
+        self.name_to_id.keys().any(|seated| same_name(seated, name))
+            || self.bot_names.iter().any(|bot| same_name(bot, name))
+            || self.waiting_line.iter().any(|(_, waiting_name)| same_name(waiting_name, name))
+    }
+
+    fn waiting_position(&self, connection_id: &str) -> Option<(usize, String)> {
+        self.waiting_line.iter()
+            .position(|(id, _)| id == connection_id)
+            .map(|index| (index + 1, self.waiting_line[index].1.clone()))
+    }
+
+    pub fn seat_participant(&mut self, connection_id: &str, name: &str) {
+        self.name_to_id.insert(name.to_string(), connection_id.to_string());
+        self.id_to_name.insert(connection_id.to_string(), name.to_string());
+        self.name_to_color.insert(name.to_string(), name_to_color(name));
+
+        self.typing_state.insert(name.to_string(), Message {
+            id: 0,
+            name: name.to_string(),
+            content: "".to_string(),
+            color: name_to_color(name),
+            connection_id: connection_id.to_string(),
+            encrypted: self.encrypted,
+            idle: false,
+            has_replay: false,
+            hidden: self.drafts_hidden(connection_id),
+            sent_at: OffsetDateTime::now_utc(),
+            kind: MessageKind::Chat,
+            mentions: Vec::new(),
+        });
+
+        if let Err(e) = self.tx.send(ActionEvent {
+            connection_id: connection_id.to_string(),
+            action: Action::SetName,
+        }) {
+            println!("Error broadcasting name change: {}", e);
+        }
+        self.notify_bots(BotEvent::Joined { name: name.to_string() });
+        self.notify_webhooks(WebhookEvent::ParticipantJoined { name: name.to_string() });
+    }
+
+    fn fill_open_seats(&mut self) {
+        let mut promoted = false;
+        while !self.is_full() {
+            let Some((connection_id, name)) = self.waiting_line.pop_front() else {
+                break;
+            };
+            self.seat_participant(&connection_id, &name);
+            promoted = true;
+        }
+
+        if promoted {
+            let _ = self.tx.send(ActionEvent {
+                connection_id: "System".to_string(),
+                action: Action::WaitingLine,
+            });
+        }
+    }
+
+    pub fn extend(&mut self, by: Duration) {
+        let now = Instant::now();
+        self.expiration = (self.expiration.max(now) + by).min(now + MAX_ROOM_LIFETIME);
+        self.last_warning = None;
+        if self.grace_until.take().is_some() {
+            self.reopen_polls();
+        }
+
+        if let Err(e) = self.tx.send(ActionEvent {
+            connection_id: "System".to_string(),
+            action: Action::Extended,
+        }) {
+            println!("Error broadcasting extend event {}", e);
+        }
+    }
+
+    // checked every cleanup tick, spectators alone don't keep a room open
+    fn idle_shutdown(&mut self, now: Instant) -> Option<ShutdownReason> {
+        // closed tabs leave their entry behind at 0 unless the room has a seat limit
+        let occupied = self.connections.iter()
+            .any(|(id, count)| *count > 0 && !self.spectators.contains(id));
+        if occupied {
+            self.empty_since = None;
+        } else {
+            let empty_since = *self.empty_since.get_or_insert(now);
+            if self.close_when_empty.is_some_and(|timeout| now.duration_since(empty_since) >= timeout) {
+                return Some(ShutdownReason::Empty);
+            }
+        }
+
+        if self.close_when_quiet.is_some_and(|timeout| now.duration_since(self.last_message_at) >= timeout) {
+            return Some(ShutdownReason::Quiet);
+        }
+        None
+    }
+
+    // the tightest warning threshold crossed since the last one went out
+    fn due_warning(&mut self, thresholds: &[u64]) -> Option<u64> {
+        let remaining = self.expiration.saturating_duration_since(Instant::now()).as_secs();
+        let crossed = thresholds.iter()
+            .copied()
+            .filter(|threshold| remaining <= *threshold)
+            .filter(|threshold| self.last_warning.is_none_or(|last| *threshold < last))
+            .min()?;
+        self.last_warning = Some(crossed);
+        Some(remaining)
+    }
+
+    // encrypted transcripts would only be ciphertext
+    fn can_export(&self) -> bool {
+        !self.exports_disabled && !self.encrypted
+    }
+
+    // once anyone has written something there's a key in use, a new one would only lock the owner out of it
+    fn has_ciphertext(&self) -> bool {
+        self.message_history.iter().any(|message| message.kind != MessageKind::System)
+            || self.typing_state.values().any(|message| !message.content.is_empty())
+    }
+
+    fn drafts_hidden(&self, connection_id: &str) -> bool {
+        self.hidden_typing || self.hidden_typists.contains(connection_id)
+    }
+
+    fn chat_input(&self, room_id: &str, connection_id: &str, name: &str) -> ChatInputTemplate {
+        ChatInputTemplate {
+            room_id: room_id.to_string(),
+            person: name.to_string(),
+            encrypted: self.encrypted,
+            room_hides_typing: self.hidden_typing,
+            hide_typing: self.drafts_hidden(connection_id),
+        }
+    }
+
+    fn clear_typing(&mut self, name: &str) {
+        if let Some(message) = self.typing_state.get_mut(name) {
+            if message.content.is_empty() && !message.idle {
+                return;
+            }
+            message.content.clear();
+            message.idle = false;
+            self.typing_seqs.remove(name);
+            self.recorder.discard(name);
+            self.dirty_typists.insert(name.to_string());
+        }
+    }
+
+    // badge drafts nobody has touched in a while, then clear them out entirely
+    fn expire_idle_typing(&mut self, idle_after: Duration, clear_after: Duration) {
+        let now = Instant::now();
+        let mut to_clear = Vec::new();
+        for (name, message) in self.typing_state.iter_mut() {
+            if message.content.is_empty() {
+                continue;
+            }
+            let quiet_for = self.last_typed.get(name)
+                .map(|last_typed| now.duration_since(*last_typed))
+                .unwrap_or(clear_after);
+            if quiet_for >= clear_after {
+                to_clear.push(name.clone());
+            } else if quiet_for >= idle_after && !message.idle {
+                message.idle = true;
+                self.dirty_typists.insert(name.clone());
+            }
+        }
+
+        for name in to_clear {
+            self.clear_typing(&name);
+        }
+    }
+
+    /// Counts another connection, tabs and gateway clients alike, and subscribes it to the room's events.
+    pub fn connect(&mut self, connection_id: &str) -> broadcast::Receiver<ActionEvent> {
+        self.join_count += 1;
+        *self.connections.entry(connection_id.to_string()).or_insert(0) += 1;
+        self.tx.subscribe()
+    }
+
+    // closed tabs leave their entry at 0 for the seat release grace, so only a live count is connected
+    fn is_connected(&self, connection_id: &str) -> bool {
+        self.connections.get(connection_id).is_some_and(|count| *count > 0)
+    }
+
+    /// Forgets the connection and gives up its seat or place in line.
+    pub fn disconnect(&mut self, connection_id: &str) {
+        self.connections.remove(connection_id);
+        self.release_participant(connection_id);
+    }
+
+    fn release_participant(&mut self, connection_id: &str) {
+        if let Some(name) = self.id_to_name.remove(connection_id) {
+            self.name_to_id.remove(&name);
+            self.name_to_color.remove(&name);
+            self.typing_state.remove(&name);
+            self.typing_seqs.remove(&name);
+            self.last_typed.remove(&name);
+            self.recorder.discard(&name);
+            self.hidden_typists.remove(connection_id);
+
+            // clear their typing box for everyone else
+            let _ = self.tx.send(ActionEvent {
+                connection_id: connection_id.to_string(),
+                action: Action::Typing,
+            });
+        }
+
+        let line_length = self.waiting_line.len();
+        self.waiting_line.retain(|(id, _)| id != connection_id);
+        if self.waiting_line.len() != line_length {
+            let _ = self.tx.send(ActionEvent {
+                connection_id: "System".to_string(),
+                action: Action::WaitingLine,
+            });
+        }
+
+        self.fill_open_seats();
+    }
+}
+
+// tracks an open SSE stream, dropped along with the stream when the client goes away
+struct ConnectionGuard {
+    state: Arc<AllRooms>,
+    room_id: String,
+    connection_id: String,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let state = self.state.clone();
+        let room_id = self.room_id.clone();
+        let connection_id = self.connection_id.clone();
+        tokio::spawn(async move {
+            disconnect_from_room(state, room_id, connection_id).await;
+        });
+    }
 }
 
 #[derive(Clone)]
 pub struct Message {
+    // 0 for typing buffers, chat log messages count up from 1
+    pub id: u64,
     pub name: String,
     pub connection_id: String,
     pub color: String,
     pub content: String,
+    pub encrypted: bool,
+    // typing buffers only, shown with a badge once the typist goes quiet
+    pub idle: bool,
+    pub has_replay: bool,
+    // typing buffers only, only the typist's name is shown and the content is just a placeholder
+    pub hidden: bool,
+    // last keystroke for typing buffers
+    pub sent_at: OffsetDateTime,
+    pub kind: MessageKind,
+    // @names resolved when the message was sent
+    pub mentions: Vec<Mention>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageKind {
+    Chat,
+    // posted by one of the room's bots under its reserved name
+    Bot,
+    // /me, shown as "name does something"
+    Emote,
+    // announcements from the room itself, like renames and kicks
+    System,
+    // sent from a script with the room's post token
+    Webhook,
+}
+
+impl Message {
+    pub fn is_bot(&self) -> bool {
+        self.kind == MessageKind::Bot
+    }
+
+    pub fn is_webhook(&self) -> bool {
+        self.kind == MessageKind::Webhook
+    }
+
+    pub fn is_emote(&self) -> bool {
+        self.kind == MessageKind::Emote
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.kind == MessageKind::System
+    }
+
+    pub fn parts(&self) -> Vec<Part<'_>> {
+        mentions::parts(&self.content, &self.mentions)
+    }
+
+    // mentioning yourself doesn't call for your own attention
+    pub fn mentions_connection(&self, connection_id: &str) -> bool {
+        self.connection_id != connection_id
+            && self.mentions.iter().any(|mention| mention.connection_id == connection_id)
+    }
+
+    // names can hold anything, so hex them into something safe for an element id
+    pub fn typing_id(&self) -> String {
+        let hex_name: String = self.name.bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("typing-{}", hex_name)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -106,75 +1081,465 @@ pub struct RoomParams {
     pub room_id: String,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct TypingRequest {
-    pub message: String,
-}
+#[derive(Debug, Deserialize)]
+pub struct SpectatorParams {
+    pub spectator_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TypingRequest {
+    pub message: String,
+}
+
+// either the whole buffer, or edits on top of what the server already has
+#[derive(Debug, Deserialize)]
+pub struct LiveTypingRequest {
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub seq: Option<u64>,
+    #[serde(default)]
+    pub ops: Vec<TypingOp>,
+}
+
+// positions and lengths count unicode scalar values, not bytes
+#[derive(Debug, Deserialize)]
+pub struct TypingOp {
+    pub pos: usize,
+    #[serde(default)]
+    pub delete: usize,
+    #[serde(default)]
+    pub insert: String,
+}
+
+fn apply_typing_ops(content: &str, ops: &[TypingOp]) -> Option<String> {
+    let mut chars: Vec<char> = content.chars().collect();
+    for op in ops {
+        let end = op.pos.checked_add(op.delete)?;
+        if end > chars.len() {
+            return None;
+        }
+        chars.splice(op.pos..end, op.insert.chars());
+    }
+    Some(chars.into_iter().collect())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetNameRequest {
+    pub name: String,
+    #[serde(default)]
+    pub invite: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteParams {
+    #[serde(default)]
+    pub invite: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinParams {
+    pub room_id: String,
+    pub message_id: u64,
+}
+
+/// Whether new messages in a background tab beep or show a notification, the unread count is always on.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct AlertPrefs {
+    #[serde(rename = "alertSound")]
+    pub sound: bool,
+    #[serde(rename = "alertNotify")]
+    pub notify: bool,
+}
+
+impl AlertPrefs {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let value = get_cookie(headers, ALERTS_COOKIE).unwrap_or_default();
+        Self {
+            sound: value.split(',').any(|pref| pref == "sound"),
+            notify: value.split(',').any(|pref| pref == "notify"),
+        }
+    }
+
+    fn cookie(&self) -> String {
+        let prefs: Vec<&str> = [(self.sound, "sound"), (self.notify, "notify")].into_iter()
+            .filter_map(|(on, pref)| on.then_some(pref))
+            .collect();
+        format!("{}={}; Path=/; Max-Age=31536000; SameSite=Lax", ALERTS_COOKIE, prefs.join(","))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PollParams {
+    pub room_id: String,
+    pub poll_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VoteParams {
+    pub room_id: String,
+    pub poll_id: u64,
+    pub option: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PollRequest {
+    #[serde(rename = "pollQuestion")]
+    pub question: String,
+    // one option per line
+    #[serde(rename = "pollOptions")]
+    pub options: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopicRequest {
+    #[serde(rename = "topicText")]
+    pub topic: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayParams {
+    pub room_id: String,
+    pub message_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpectatorReplayParams {
+    pub spectator_key: String,
+    pub message_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteTokenParams {
+    pub room_id: String,
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    pub room_id: String,
+    pub format: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpectatorExportParams {
+    pub spectator_key: String,
+    pub format: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchRequest {
+    #[serde(rename = "searchText", default)]
+    pub text: String,
+    #[serde(rename = "searchAuthor", default)]
+    pub author: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TypingVisibilityRequest {
+    #[serde(rename = "hideTyping")]
+    pub hide_typing: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostMessageRequest {
+    pub name: String,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExtendRequest {
+    #[serde(rename = "extendMinutes", deserialize_with = "number_or_string")]
+    pub minutes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteRequest {
+    #[serde(rename = "inviteUses", deserialize_with = "number_or_string")]
+    pub max_uses: u64,
+    #[serde(rename = "inviteMinutes", deserialize_with = "number_or_string")]
+    pub minutes: u64,
+}
+
+// bound inputs can hand signals over as either numbers or strings
+fn number_or_string<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(u64),
+        String(String),
+    }
+
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::String(string) => string.trim().parse().map_err(serde::de::Error::custom),
+    }
+}
+
+pub struct AllRooms {
+    pub rooms: Mutex<HashMap<String, Room>>,
+    // signs creation tokens, regenerated on every start
+    secret: [u8; 32],
+    config: Config,
+    webhooks: Webhooks,
+    // when each address created rooms through the API over the last minute
+    api_creations: Mutex<HashMap<IpAddr, Vec<Instant>>>,
+}
+
+impl AllRooms {
+    pub fn new(config: Config) -> Arc<Self> {
+        let webhooks = Webhooks::new(global_webhooks(&config));
+        let rooms = Arc::new(Self {
+            rooms: Mutex::new(HashMap::new()),
+            secret: rand::random(),
+            config,
+            webhooks,
+            api_creations: Mutex::new(HashMap::new()),
+        });
+
+        let rooms_cleanup = rooms.clone();
+        tokio::spawn(async move {
+            cleanup_rooms(rooms_cleanup).await;
+        });
+
+        let rooms_typing = rooms.clone();
+        tokio::spawn(async move {
+            flush_typing(rooms_typing).await;
+        });
+
+        rooms
+    }
+
+    /// Creates a room and returns its signed creation token, or None if the room already exists.
+    pub async fn create_room(
+        self: &Arc<Self>,
+        room_id: &str,
+        hours: u64,
+        minutes: u64,
+        settings: RoomSettings,
+    ) -> Option<String> {
+        let mut rooms = self.rooms.lock().await;
+        if rooms.contains_key(room_id) {
+            return None;
+        }
+
+        let clamped_hours = hours.clamp(0, 12);
+        let clamped_minutes = minutes.clamp(0, 60);
+
+        let settings = RoomSettings {
+            max_participants: settings.max_participants
+                .filter(|max| *max > 0)
+                .map(|max| max.min(MAX_PARTICIPANTS)),
+            title: Some(settings.title.trim().chars().take(MAX_TITLE_LENGTH).collect::<String>())
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| room_id.to_string()),
+            ..settings
+        };
+
+        // bots can't read ciphertext, so encrypted rooms don't get any
+        let bots: Vec<Box<dyn Bot>> = if settings.encrypted {
+            Vec::new()
+        } else {
+            settings.bots.iter().filter_map(|key| bots::build(key)).collect()
+        };
+
+        let webhook_url = settings.webhook_url.clone();
+        let mut room = Room::new(
+            Instant::now() + Duration::from_secs(clamped_hours * 60 * 60) + Duration::from_secs(clamped_minutes * 60),
+            settings,
+        );
+        if !bots.is_empty() {
+            let (bot_tx, bot_rx) = mpsc::unbounded_channel();
+            room.bot_names = bots.iter().map(|bot| bot.name()).collect();
+            room.bot_tx = Some(bot_tx);
+            tokio::spawn(bots::run_bots(self.clone(), room_id.to_string(), bots, bot_rx));
+        }
+        let room_target = webhooks::parse_url(&webhook_url)
+            .filter(|_| self.config.room_webhooks)
+            .map(|url| WebhookTarget {
+                url,
+                secret: sign(&self.secret, &format!("webhook:{}:{}", room_id, room.creation_nonce)),
+            });
+        room.webhook_secret = room_target.as_ref().map(|target| target.secret.clone());
+        room.webhooks = self.webhooks.for_room(room_id, room_target);
+        room.notify_webhooks(WebhookEvent::RoomCreated { title: room.title.clone() });
+
+        let creation_token = self.creation_token(room_id, &room);
+        rooms.insert(room_id.to_string(), room);
+
+        Some(creation_token)
+    }
+
+    /// The bearer token scripts post messages with, None for encrypted rooms.
+    pub async fn room_post_token(&self, room_id: &str) -> Option<String> {
+        let rooms = self.rooms.lock().await;
+        self.post_token(room_id, rooms.get(room_id)?)
+    }
+
+    /// Counts a room created through the API, false once the address is over its limit for the minute.
+    pub async fn allow_api_creation(&self, address: IpAddr) -> bool {
+        let limit = self.config.api_rooms_per_minute;
+        if limit == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let mut creations = self.api_creations.lock().await;
+        creations.retain(|_, times| {
+            times.retain(|time| now.duration_since(*time) < API_CREATION_WINDOW);
+            !times.is_empty()
+        });
+        let times = creations.entry(address).or_default();
+        if times.len() >= limit {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+
+    /// The key a room's own webhook deliveries are signed with, if it has one.
+    pub async fn webhook_secret(&self, room_id: &str) -> Option<String> {
+        self.rooms.lock().await.get(room_id)?.webhook_secret.clone()
+    }
+
+    fn creation_token(&self, room_id: &str, room: &Room) -> String {
+        sign(&self.secret, &format!("create:{}:{}", room_id, room.creation_nonce))
+    }
+
+    // encrypted rooms only carry ciphertext, so scripts can't post into them
+    fn post_token(&self, room_id: &str, room: &Room) -> Option<String> {
+        Some(sign(&self.secret, &format!("post:{}:{}", room_id, room.creation_nonce)))
+            .filter(|_| !room.encrypted)
+    }
 
-#[derive(Debug, Deserialize)]
-pub struct SetNameRequest {
-    pub name: String,
+    fn is_owner(&self, headers: &HeaderMap, room_id: &str, room: &Room) -> bool {
+        get_cookie(headers, "impermachat_owner")
+            .is_some_and(|token| verify_signature(
+                &self.secret,
+                &format!("create:{}:{}", room_id, room.creation_nonce),
+                &token,
+            ))
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ExpirationParams {
-    #[serde(default)]
-    pub hours: Option<u64>,
-    #[serde(default)]
-    pub minutes: Option<u64>,
+// global webhooks need a shared secret, otherwise receivers can't tell real deliveries apart
+fn global_webhooks(config: &Config) -> Vec<WebhookTarget> {
+    if config.webhook_urls.is_empty() {
+        return Vec::new();
+    }
+    let Some(secret) = &config.webhook_secret else {
+        println!("Ignoring IMPERMACHAT_WEBHOOK_URLS, set IMPERMACHAT_WEBHOOK_SECRET to sign them");
+        return Vec::new();
+    };
+    config.webhook_urls.iter()
+        .filter_map(|url| match webhooks::parse_url(url) {
+            Some(url) => Some(WebhookTarget { url, secret: secret.clone() }),
+            None => {
+                println!("Ignoring invalid webhook URL {}", url);
+                None
+            },
+        })
+        .collect()
 }
 
-pub struct AllRooms {
-    pub rooms: Mutex<HashMap<String, Room>>,
+// names are told apart the way mentions find them, so "Sam" and "sam" can't both be in a room
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
 }
 
-impl AllRooms {
-    pub fn new() -> Arc<Self> {
-        let rooms = Arc::new(Self {
-            rooms: Mutex::new(HashMap::new()),
-        });
-
-        let rooms_cleanup = rooms.clone();
-        tokio::spawn(async move {
-            cleanup_rooms(rooms_cleanup).await;
-        });
+fn clean_topic(topic: &str) -> Option<String> {
+    Some(topic.trim().chars().take(MAX_TOPIC_LENGTH).collect::<String>())
+        .filter(|topic| !topic.is_empty())
+}
 
-        rooms
-    }
+// invite only rooms have no spectator link, watching would get around the invites
+fn room_id_for_spectator_key(rooms: &HashMap<String, Room>, spectator_key: &str) -> Option<String> {
+    rooms.iter()
+        .find(|(_, room)| room.spectator_key == spectator_key && !room.invite_only)
+        .map(|(room_id, _)| room_id.clone())
 }
 
 pub async fn render_room(
+    headers: HeaderMap,
     Path(RoomParams { room_id }): Path<RoomParams>,
-    Query(ExpirationParams { hours, minutes }): Query<ExpirationParams>,
+    Query(InviteParams { invite }): Query<InviteParams>,
     State(state): State<Arc<AllRooms>>,
 ) -> impl IntoResponse { 
-    let mut rooms = state.rooms.lock().await;
-    if let Some(_room) = rooms.get_mut(&room_id) {
+    let rooms = state.rooms.lock().await;
+    if let Some(room) = rooms.get(&room_id) {
+        let connection_id = get_connection_cookie(&headers);
+        let is_owner = state.is_owner(&headers, &room_id, room);
+        if !room.can_view(connection_id.as_deref(), is_owner, invite.as_deref()) {
+            return (StatusCode::FORBIDDEN, InviteRequiredTemplate {}).into_response();
+        }
+
         RoomTemplate{
             room_id: room_id.clone(),
+            title: room.title.clone(),
+            spectator_key: room.spectator_key.clone(),
+            spectator: false,
+            is_owner,
+            can_create_key: is_owner && !room.has_ciphertext(),
+            invite: invite.unwrap_or_default(),
+            encrypted: room.encrypted,
+            can_export: room.can_export(),
+            topic: room.topic.clone(),
+            invite_only: room.invite_only,
+            post_token: state.post_token(&room_id, room).filter(|_| is_owner).unwrap_or_default(),
+            alerts: AlertPrefs::from_headers(&headers),
         }.into_response()
     } else {
-        if hours.is_none() && minutes.is_none() {
-            return Redirect::to("/").into_response();
-        }
+        (StatusCode::NOT_FOUND, RoomNotFoundTemplate {}).into_response()
+    }
+}
 
-        let clamped_hours = hours.unwrap_or(0).clamp(0, 12);
-        let clamped_minutes = minutes.unwrap_or(0).clamp(0, 60);
+pub async fn render_spectator_room(
+    headers: HeaderMap,
+    Path(SpectatorParams { spectator_key }): Path<SpectatorParams>,
+    State(state): State<Arc<AllRooms>>,
+) -> impl IntoResponse {
+    let rooms = state.rooms.lock().await;
+    match room_id_for_spectator_key(&rooms, &spectator_key) {
+        Some(room_id) => RoomTemplate {
+            title: rooms[&room_id].title.clone(),
+            encrypted: rooms[&room_id].encrypted,
+            can_export: rooms[&room_id].can_export(),
+            topic: rooms[&room_id].topic.clone(),
+            invite_only: false,
+            post_token: "".to_string(),
+            alerts: AlertPrefs::from_headers(&headers),
+            // the watch page never needs it, and it's all it takes to join
+            room_id: "".to_string(),
+            spectator_key,
+            spectator: true,
+            is_owner: false,
+            can_create_key: false,
+            invite: "".to_string(),
+        }.into_response(),
+        None => Redirect::to("/").into_response(),
+    }
+}
 
-        let (tx, _rx) = broadcast::channel(100);
-        rooms.insert(room_id.clone(), Room{
-            tx,
-            message_history: Vec::new(),
-            join_count: 1,
-            name_to_id: HashMap::new(),
-            id_to_name: HashMap::new(),
-            name_to_color: HashMap::new(),
-            typing_state: HashMap::new(),
-            expiration: Instant::now() + Duration::from_secs(clamped_hours * 60 * 60) + Duration::from_secs(clamped_minutes * 60),
-        });
-        RoomTemplate{
-            room_id: room_id.clone(),
-        }.into_response()
+// merges bursts of keystrokes into one broadcast per room per frame
+async fn flush_typing(all_rooms: Arc<AllRooms>) {
+    let mut frames = interval(all_rooms.config.typing_frame_interval());
+    frames.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        frames.tick().await;
+
+        let mut rooms = all_rooms.rooms.lock().await;
+        for room in rooms.values_mut() {
+            if room.dirty_typists.is_empty() {
+                continue;
+            }
+            let typists = room.dirty_typists.drain().collect();
+            let _ = room.tx.send(ActionEvent {
+                connection_id: "System".to_string(),
+                action: Action::TypingFrame(typists),
+            });
+        }
     }
 }
 
@@ -183,26 +1548,55 @@ async fn cleanup_rooms(all_rooms: Arc<AllRooms>) {
         sleep(Duration::from_secs(1)).await;
 
         let mut rooms = all_rooms.rooms.lock().await;
-        let mut to_remove = Vec::new();
+        let mut to_remove: Vec<(String, ShutdownReason)> = Vec::new();
 
         for (room_id, room) in rooms.iter_mut() {
-            if Instant::now() > room.expiration {
+            let now = Instant::now();
+            if let Some(reason) = room.idle_shutdown(now) {
+                let _ = room.tx.send(ActionEvent {
+                    connection_id: "System".to_string(),
+                    action: Action::ShutdownRoom(reason),
+                });
+                to_remove.push((room_id.clone(), reason));
+            } else if now > room.expiration && room.grace_until.is_none() && !all_rooms.config.expiry_grace().is_zero() {
+                // time's up, give the owner one last chance to extend
+                let grace = all_rooms.config.expiry_grace();
+                room.grace_until = Some(now + grace);
+                room.close_polls();
+                let _ = room.tx.send(ActionEvent {
+                    connection_id: "System".to_string(),
+                    action: Action::FinalCountdown(grace.as_secs()),
+                });
+            } else if now > room.grace_until.unwrap_or(room.expiration) {
                 // broadcast room shutdown
                 let _ = room.tx.send(ActionEvent {
                     connection_id: "System".to_string(),
-                    action: Action::ShutdownRoom,
+                    action: Action::ShutdownRoom(ShutdownReason::Expired),
                 });
-                to_remove.push(room_id.clone());
+                to_remove.push((room_id.clone(), ShutdownReason::Expired));
             } else {
+                if let Some(remaining) = room.due_warning(&all_rooms.config.expiry_warning_secs) {
+                    let _ = room.tx.send(ActionEvent {
+                        connection_id: "System".to_string(),
+                        action: Action::ExpiryWarning(remaining),
+                    });
+                }
                 let _ = room.tx.send(ActionEvent {
                     connection_id: "System".to_string(),
                     action: Action::UpdateTime,
                 });
+                room.expire_idle_typing(
+                    all_rooms.config.typing_idle_after(),
+                    all_rooms.config.typing_clear_after(),
+                );
             }
         }
 
-        for room_id in to_remove {
-            rooms.remove(&room_id);
+        for (room_id, reason) in to_remove {
+            if let Some(room) = rooms.remove(&room_id) {
+                room.notify_bots(BotEvent::Shutdown);
+                room.notify_webhooks(WebhookEvent::RoomShutDown { reason: reason.key() });
+            }
         }
     }
 }
@@ -210,89 +1604,261 @@ async fn cleanup_rooms(all_rooms: Arc<AllRooms>) {
 pub async fn connect_to_room(
     headers: HeaderMap,
     Path(RoomParams { room_id }): Path<RoomParams>,
+    Query(InviteParams { invite }): Query<InviteParams>,
     State(state): State<Arc<AllRooms>>,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+) -> Response<Body> {
 
     // get this person's uid
     let connection_id = get_connection_cookie(&headers)
         .expect("Middleware should have bestowed UUID by now.");
 
-    // check for existing room or create 0 second one (want to redirect to index instead)
-    let rx = {
+    let is_owner = {
+        let mut rooms = state.rooms.lock().await;
+        match rooms.get_mut(&room_id) {
+            Some(room) => {
+                let is_owner = state.is_owner(&headers, &room_id, room);
+                if !room.admit(&connection_id, is_owner, invite.as_deref()) {
+                    return (
+                        StatusCode::OK,
+                        [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+                        "event: datastar-merge-fragments\ndata: fragments <div id='chat-container'><h1 class='shutdown-message'>This room needs a valid invite</h1></div>\n\n"
+                    ).into_response();
+                }
+                is_owner
+            },
+            None => false,
+        }
+    };
+
+    room_stream(state, room_id, connection_id, false, is_owner).await
+}
+
+pub async fn watch_room(
+    headers: HeaderMap,
+    Path(SpectatorParams { spectator_key }): Path<SpectatorParams>,
+    State(state): State<Arc<AllRooms>>,
+) -> Response<Body> {
+    let connection_id = get_connection_cookie(&headers)
+        .expect("Middleware should have bestowed UUID by now.");
+
+    let room_id = {
         let mut rooms = state.rooms.lock().await;
+        let Some(room_id) = room_id_for_spectator_key(&rooms, &spectator_key) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        // people already seated keep their seat, everyone else is locked into watching
         if let Some(room) = rooms.get_mut(&room_id) {
-            room.join_count = room.join_count + 1;
-            room.tx.subscribe()
-        } else {
-            let (tx, rx) = broadcast::channel(100);
-            rooms.insert(room_id.clone(), Room{
-                tx,
-                message_history: Vec::new(),
-                join_count: 1,
-                name_to_id: HashMap::new(),
-                id_to_name: HashMap::new(),
-                name_to_color: HashMap::new(),
-                typing_state: HashMap::new(),
-                expiration: Instant::now() + Duration::from_secs(0),
-            });
-            rx
+            if !room.id_to_name.contains_key(&connection_id) {
+                room.waiting_line.retain(|(id, _)| id != &connection_id);
+                room.spectators.insert(connection_id.clone());
+            }
+        }
+        room_id
+    };
+
+    room_stream(state, room_id, connection_id, true, false).await
+}
+
+pub async fn replay_message(
+    headers: HeaderMap,
+    Path(ReplayParams { room_id, message_id }): Path<ReplayParams>,
+    State(state): State<Arc<AllRooms>>,
+) -> Response<Body> {
+    let connection_id = get_connection_cookie(&headers)
+        .expect("Middleware should have bestowed UUID by now.");
+
+    replay_stream(state, room_id, connection_id, message_id).await
+}
+
+pub async fn watch_replay_message(
+    headers: HeaderMap,
+    Path(SpectatorReplayParams { spectator_key, message_id }): Path<SpectatorReplayParams>,
+    State(state): State<Arc<AllRooms>>,
+) -> Response<Body> {
+    let connection_id = get_connection_cookie(&headers)
+        .expect("Middleware should have bestowed UUID by now.");
+
+    let Some(room_id) = room_id_for_spectator_key(&*state.rooms.lock().await, &spectator_key) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    replay_stream(state, room_id, connection_id, message_id).await
+}
+
+// plays a recorded draft back frame by frame into the replay panel
+async fn replay_stream(
+    state: Arc<AllRooms>,
+    room_id: String,
+    connection_id: String,
+    message_id: u64,
+) -> Response<Body> {
+    let (message, snapshots) = {
+        let rooms = state.rooms.lock().await;
+        let Some(room) = rooms.get(&room_id) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        // only people already connected to the room (and so past its invite check) can replay
+        if !room.is_connected(&connection_id) {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        let message = room.message_history.iter().find(|message| message.id == message_id).cloned();
+        match (message, room.recorder.replay(message_id)) {
+            (Some(message), Some(snapshots)) => (message, snapshots),
+            _ => return StatusCode::NOT_FOUND.into_response(),
         }
     };
 
+    replay_events(message, snapshots).into_response()
+}
+
+fn replay_events(message: Message, snapshots: Vec<Snapshot>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     let stream = try_stream! {
-        // flush
-        yield Event::default().data("");
+        let mut previous = Duration::ZERO;
+        for snapshot in snapshots {
+            sleep(snapshot.offset.saturating_sub(previous).min(MAX_REPLAY_PAUSE)).await;
+            previous = snapshot.offset;
 
-        let room = state.rooms.lock().await
-            .get(&room_id)
-            .cloned()
-            .expect("Room should exist by now");
+            yield Event::default()
+                .event("datastar-merge-fragments")
+                .data(create_fragments_event(ReplayTemplate {
+                    message: Message {
+                        content: snapshot.content,
+                        mentions: Vec::new(),
+                        ..message.clone()
+                    },
+                    done: false,
+                }.render().unwrap()));
+        }
 
         yield Event::default()
             .event("datastar-merge-fragments")
-            .data(SubmitTemplate {
-                    messages: room.message_history.clone(),
-                    connection_id: connection_id.clone(),
-            }.render().unwrap());
+            .data(create_fragments_event(ReplayTemplate {
+                message,
+                done: true,
+            }.render().unwrap()));
+    };
+
+    Sse::new(stream)
+}
 
+async fn room_stream(
+    state: Arc<AllRooms>,
+    room_id: String,
+    connection_id: String,
+    spectator: bool,
+    is_owner: bool,
+) -> Response<Body> {
+    // only rooms created through the index form or API can be joined
+    let rx = {
+        let mut rooms = state.rooms.lock().await;
+        let Some(room) = rooms.get_mut(&room_id) else {
+            return (
+                StatusCode::OK,
+                [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+                format!("event: datastar-merge-fragments\ndata: {}\n\n", ShutdownTemplate { reason: None }.render().unwrap())
+            ).into_response();
+        };
+        room.connect(&connection_id)
+    };
+
+    let guard = ConnectionGuard {
+        state: state.clone(),
+        room_id: room_id.clone(),
+        connection_id: connection_id.clone(),
+    };
+
+    event_stream(state, room_id, connection_id, spectator, is_owner, rx, guard).into_response()
+}
+
+// everything a new connection needs to catch up, rendered while the room is locked
+fn initial_events(room: &Room, room_id: &str, connection_id: &str, spectator: bool, is_owner: bool) -> Vec<Event> {
+    let mut events = vec![
         // render typing state
-        yield Event::default()
+        Event::default()
             .event("datastar-merge-fragments")
             .data(TypingTemplate {
                 messages: room.typing_state.clone(),
-                connection_id: connection_id.clone(),
-            }.render().unwrap());
-
+                connection_id: connection_id.to_string(),
+            }.render().unwrap()),
         // populate existing messages
-        let initial_messages = SubmitTemplate {
-            messages: room.message_history.clone(),
-            connection_id: connection_id.clone(),
-        }.render().unwrap();
-        let mut raw_event = String::from("");
-        for line in initial_messages.lines() {
-            raw_event.push_str(&format!("fragments {}\n", line));
-        }
-        yield Event::default()
+        Event::default()
             .event("datastar-merge-fragments")
-            .data(raw_event);
-
-        // check if person has already selected a name in this room
-        match room.id_to_name.get(&connection_id) {
-            Some(name) => {
-                yield Event::default()
-                    .event("datastar-merge-fragments")
-                    .data(ChatInputTemplate {
-                        room_id: room_id.clone(),
-                        person: name.to_string(),
-                    }.render().unwrap())
-            },
-            None => {
-                yield Event::default()
-                    .event("datastar-merge-fragments")
-                    .data(InitNameTemplate {
-                        room_id: room_id.clone(),
-                    }.render().unwrap());
-            }
+            .data(create_fragments_event(SubmitTemplate {
+                messages: room.message_history.clone(),
+                connection_id: connection_id.to_string(),
+                can_pin: is_owner,
+            }.render().unwrap())),
+        Event::default()
+            .event("datastar-merge-fragments")
+            .data(create_fragments_event(PinnedTemplate {
+                messages: room.pinned_messages(),
+                can_unpin: is_owner,
+            }.render().unwrap())),
+        Event::default()
+            .event("datastar-merge-fragments")
+            .data(create_fragments_event(PollsTemplate {
+                polls: room.poll_views(connection_id, is_owner),
+            }.render().unwrap())),
+    ];
+
+    // people joining during the last seconds still need to see it's closing
+    if let Some(grace_until) = room.grace_until {
+        events.push(Event::default()
+            .event("datastar-merge-fragments")
+            .data(create_fragments_event(ExpiryWarningTemplate {
+                room_id: room_id.to_string(),
+                remaining: format_remaining(grace_until.saturating_duration_since(Instant::now()).as_secs()),
+                final_countdown: true,
+                is_owner,
+            }.render().unwrap())));
+    }
+
+    // check if person has already selected a name in this room
+    let chat_control = match room.id_to_name.get(connection_id) {
+        _ if spectator || room.is_spectator(connection_id) => SpectatorTemplate {}.render().unwrap(),
+        Some(name) => room.chat_input(room_id, connection_id, name).render().unwrap(),
+        None => match room.waiting_position(connection_id) {
+            Some((position, name)) => WaitingTemplate {
+                position,
+                person: name,
+            }.render().unwrap(),
+            None => InitNameTemplate {
+                room_id: room_id.to_string(),
+            }.render().unwrap(),
+        },
+    };
+    events.push(Event::default()
+        .event("datastar-merge-fragments")
+        .data(chat_control));
+
+    events
+}
+
+fn event_stream(
+    state: Arc<AllRooms>,
+    room_id: String,
+    connection_id: String,
+    spectator: bool,
+    is_owner: bool,
+    rx: broadcast::Receiver<ActionEvent>,
+    guard: ConnectionGuard,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = try_stream! {
+        let _guard = guard;
+
+        // flush
+        yield Event::default().data("");
+
+        // render under the lock and send afterwards, rather than copying the whole room
+        let (initial_events, mut last_seen) = {
+            let rooms = state.rooms.lock().await;
+            let room = rooms.get(&room_id).expect("Room should exist by now");
+            // newest message this connection has been sent, for the unread count
+            (initial_events(room, &room_id, &connection_id, spectator, is_owner), room.last_message_id())
+        };
+        for event in initial_events {
+            yield event;
         }
 
         // main handler loop to send SSE to update UI
@@ -311,18 +1877,56 @@ pub async fn connect_to_room(
                             .data(create_fragments_event(rendered_typing));
                     }
                 },
+                Action::TypingFrame(typists) => {
+                    let rooms = state.rooms.lock().await;
+                    if let Some(room) = rooms.get(&room_id) {
+                        // nobody sees their own typing box, skip the frame if that's all that changed
+                        let rendered_boxes: String = typists.iter()
+                            .filter_map(|name| room.typing_state.get(name).map(|message| (name, message)))
+                            .filter(|(_, message)| message.connection_id != connection_id)
+                            .map(|(name, message)| TypingBoxTemplate {
+                                person: name.clone(),
+                                message: message.clone(),
+                            }.render().unwrap())
+                            .collect::<Vec<String>>()
+                            .join("\n");
+                        if !rendered_boxes.is_empty() {
+                            yield Event::default()
+                                .event("datastar-merge-fragments")
+                                .data(create_fragments_event(rendered_boxes));
+                        }
+                    }
+                },
                 Action::Send => {
                     let mut rooms = state.rooms.lock().await;
                     if let Some(room) = rooms.get_mut(&room_id) {
                         let rendered_submit = SubmitTemplate {
                             messages: room.message_history.clone(),
                             connection_id: connection_id.clone(),
+                            can_pin: is_owner,
                         }.render().unwrap();
 
                         yield Event::default()
                             .event("datastar-merge-fragments")
                             .data(create_fragments_event(rendered_submit));
 
+                        // other people's messages count as unread while the tab is in the background
+                        let arrived: Vec<u64> = room.message_history.iter()
+                            .filter(|message| message.id > last_seen)
+                            .filter(|message| message.connection_id != connection_id && !message.is_system())
+                            .map(|message| message.id)
+                            .collect();
+                        last_seen = room.last_message_id();
+                        if let Some(newest) = arrived.last() {
+                            yield Event::default()
+                                .event("datastar-merge-fragments")
+                                .data(format!(
+                                    "fragments <div id=\"unread-alert\" data-on-load=\"impermachatAlerts.arrived({}, {})\"></div>",
+                                    newest,
+                                    arrived.len(),
+                                ));
+                        }
+
                         // clear user chat input
                         if event.connection_id == connection_id {
                             yield Event::default()
@@ -347,10 +1951,14 @@ pub async fn connect_to_room(
                             if event.connection_id == connection_id {
                                 yield Event::default()
                                     .event("datastar-merge-fragments")
-                                    .data(ChatInputTemplate {
-                                        room_id: room_id.clone(),
-                                        person: name.clone(),
-                                    }.render().unwrap())
+                                    .data(room.chat_input(&room_id, &connection_id, name).render().unwrap());
+
+                                // seated now, so the vote buttons show up
+                                yield Event::default()
+                                    .event("datastar-merge-fragments")
+                                    .data(create_fragments_event(PollsTemplate {
+                                        polls: room.poll_views(&connection_id, is_owner),
+                                    }.render().unwrap()));
                             }
 
                             // render new person's typing box
@@ -365,10 +1973,11 @@ pub async fn connect_to_room(
                             }
                     }
                 },
-                Action::ShutdownRoom => {
+                Action::ShutdownRoom(reason) => {
                     yield Event::default()
                         .event("datastar-merge-fragments")
                         .data(ShutdownTemplate {
+                            reason: Some(reason.message()),
                         }.render().unwrap());
                 },
                 Action::UpdateTime => {
@@ -376,28 +1985,161 @@ pub async fn connect_to_room(
                     if let Some(room) = rooms.get_mut(&room_id) {
                         yield Event::default()
                             .event("datastar-merge-signals")
-                            .data(format!("signals {{remaining: '{}'}}", format_time(room.expiration.duration_since(Instant::now()))));
+                            .data(format!("signals {{remaining: '{}'}}", match room.grace_until {
+                                Some(grace_until) => format!(
+                                    "Closing in {}s...",
+                                    grace_until.saturating_duration_since(Instant::now()).as_secs(),
+                                ),
+                                None => format_time(room.expiration.duration_since(Instant::now())),
+                            }));
+                    }
+                },
+                Action::ExpiryWarning(remaining) => {
+                    yield Event::default()
+                        .event("datastar-merge-fragments")
+                        .data(create_fragments_event(ExpiryWarningTemplate {
+                            room_id: room_id.clone(),
+                            remaining: format_remaining(remaining),
+                            final_countdown: false,
+                            is_owner,
+                        }.render().unwrap()));
+                },
+                Action::FinalCountdown(remaining) => {
+                    yield Event::default()
+                        .event("datastar-merge-fragments")
+                        .data(create_fragments_event(ExpiryWarningTemplate {
+                            room_id: room_id.clone(),
+                            remaining: format_remaining(remaining),
+                            final_countdown: true,
+                            is_owner,
+                        }.render().unwrap()));
+                },
+                Action::Notice(lines) => {
+                    if event.connection_id == connection_id {
+                        yield Event::default()
+                            .event("datastar-merge-fragments")
+                            .data(NoticeTemplate { lines }.render().unwrap());
+                    }
+                },
+                Action::Mentioned(message_id) => {
+                    // the id changes every time, so the alert's load handler runs again
+                    if event.connection_id == connection_id {
+                        yield Event::default()
+                            .event("datastar-merge-fragments")
+                            .data(format!("fragments <div id=\"mention-alert\" data-on-load=\"impermachatMentions.mentioned({})\"></div>", message_id));
+                    }
+                },
+                Action::Kicked => {
+                    if event.connection_id == connection_id {
+                        yield Event::default()
+                            .event("datastar-merge-fragments")
+                            .data("fragments <div id=\"chat-control\"><p>The owner removed you from the room. You can keep watching.</p></div>");
+                    }
+                },
+                Action::TopicChanged => {
+                    let rooms = state.rooms.lock().await;
+                    if let Some(room) = rooms.get(&room_id) {
+                        yield Event::default()
+                            .event("datastar-merge-fragments")
+                            .data(TopicTemplate { topic: room.topic().map(str::to_string) }.render().unwrap());
+                    }
+                },
+                Action::PinsChanged => {
+                    let rooms = state.rooms.lock().await;
+                    if let Some(room) = rooms.get(&room_id) {
+                        yield Event::default()
+                            .event("datastar-merge-fragments")
+                            .data(create_fragments_event(PinnedTemplate {
+                                messages: room.pinned_messages(),
+                                can_unpin: is_owner,
+                            }.render().unwrap()));
+                    }
+                },
+                Action::PollsChanged => {
+                    let rooms = state.rooms.lock().await;
+                    if let Some(room) = rooms.get(&room_id) {
+                        yield Event::default()
+                            .event("datastar-merge-fragments")
+                            .data(create_fragments_event(PollsTemplate {
+                                polls: room.poll_views(&connection_id, is_owner),
+                            }.render().unwrap()));
                     }
                 },
+                Action::Extended => {
+                    yield Event::default()
+                        .event("datastar-merge-fragments")
+                        .data("fragments <div id=\"expiry-warning\"></div>");
+                },
                 Action::MajorError => {
                     if event.connection_id == connection_id {
                         yield Event::default()
                             .event("datastar-merge-fragments")
                             .data(MajorErrorTemplate{}.render().unwrap());
                     }
-                }
+                },
+                Action::WaitingLine => {
+                    let rooms = state.rooms.lock().await;
+                    if let Some(room) = rooms.get(&room_id) {
+                        if let Some((position, name)) = room.waiting_position(&connection_id) {
+                            yield Event::default()
+                                .event("datastar-merge-fragments")
+                                .data(WaitingTemplate {
+                                    position,
+                                    person: name,
+                                }.render().unwrap());
+                        }
+                    }
+                },
+            }
+        }
+    };
+
+    Sse::new(stream)
+}
+
+async fn disconnect_from_room(
+    state: Arc<AllRooms>,
+    room_id: String,
+    connection_id: String,
+) {
+    {
+        let mut rooms = state.rooms.lock().await;
+        let Some(room) = rooms.get_mut(&room_id) else {
+            return;
+        };
+        if let Some(count) = room.connections.get_mut(&connection_id) {
+            *count = count.saturating_sub(1);
+            if *count > 0 {
+                return;
             }
         }
-    };
-
-    Sse::new(stream)
+
+        // last tab closed, don't leave a half written draft up for everyone
+        if let Some(name) = room.id_to_name.get(&connection_id).cloned() {
+            room.clear_typing(&name);
+        }
+
+        // only rooms with a seat limit hand out seats again
+        if room.max_participants.is_none() {
+            return;
+        }
+    }
+
+    sleep(SEAT_RELEASE_GRACE).await;
+
+    let mut rooms = state.rooms.lock().await;
+    if let Some(room) = rooms.get_mut(&room_id) {
+        if room.connections.get(&connection_id).is_some_and(|count| *count == 0) {
+            room.disconnect(&connection_id);
+        }
+    }
 }
 
 pub async fn update_room(
     headers: HeaderMap,
     State(state): State<Arc<AllRooms>>,
     Path(RoomParams { room_id }): Path<RoomParams>,
-    Json(payload): Json<TypingRequest>,
+    Json(payload): Json<LiveTypingRequest>,
 ) -> impl IntoResponse {
     let mut rooms = state.rooms.lock().await;
     if let Some(room) = rooms.get_mut(&room_id) {
@@ -412,6 +2154,10 @@ pub async fn update_room(
             }
         };
 
+        if room.is_spectator(&connection_id) {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+
         let person_name = match room.id_to_name.get(&connection_id).cloned() {
             Some(name) => name,
             None => {
@@ -425,27 +2171,120 @@ pub async fn update_room(
             }
         };
 
-        let mut new_message = payload.message.clone();
-        if payload.message.len() > MAX_MESSAGE_SIZE {
+        let hidden = room.drafts_hidden(&connection_id);
+        let typed = match payload.message {
+            // the text of a hidden draft is never kept, and without a sequence edits always ask for a resend
+            Some(message) if hidden => {
+                room.typing_seqs.remove(&person_name);
+                if message.is_empty() { String::new() } else { HIDDEN_DRAFT.to_string() }
+            },
+            Some(message) => {
+                room.typing_seqs.insert(person_name.clone(), payload.seq.unwrap_or(0));
+                message
+            },
+            None => {
+                // diffs only make sense against plaintext the server already holds, in order
+                let expected_seq = room.typing_seqs.get(&person_name).map(|seq| seq + 1);
+                let current = room.typing_state.get(&person_name)
+                    .map(|message| message.content.as_str())
+                    .unwrap_or("");
+                let applied = match (expected_seq, payload.seq) {
+                    (Some(expected), Some(seq)) if expected == seq && !room.encrypted => {
+                        apply_typing_ops(current, &payload.ops)
+                    },
+                    _ => None,
+                };
+                match applied {
+                    Some(applied) => {
+                        room.typing_seqs.insert(person_name.clone(), expected_seq.unwrap_or(0));
+                        applied
+                    },
+                    // out of sync, the client answers this by sending its whole buffer
+                    None => return StatusCode::CONFLICT.into_response(),
+                }
+            },
+        };
+
+        let mut new_message = typed;
+        if room.encrypted {
+            // can't swap in a readable warning for ciphertext, just drop it
+            if new_message.len() > MAX_ENCRYPTED_MESSAGE_SIZE {
+                return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+            }
+        } else if new_message.len() > MAX_MESSAGE_SIZE {
             new_message = "This message was too long! Keep it under 4,000 characters".to_string();
+            room.typing_seqs.remove(&person_name);
         }
 
-        room.typing_state.insert(String::from(person_name.clone()), Message{
+        room.typing_state.insert(person_name.clone(), Message{
+            id: 0,
             name: person_name.clone(),
-            content: new_message,
+            content: new_message.clone(),
             color: name_to_color(&person_name),
             connection_id: connection_id.clone(),
+            encrypted: room.encrypted,
+            idle: false,
+            has_replay: false,
+            hidden,
+            sent_at: OffsetDateTime::now_utc(),
+            kind: MessageKind::Chat,
+            mentions: Vec::new(),
         });
-        if let Err(e) = room.tx.send(ActionEvent{
-            connection_id: connection_id.clone(), 
-            action: Action::Typing
-        }) {
-            println!("Error broadcasting: {}", e);
+        if room.record_typing && !hidden {
+            room.recorder.record(&person_name, &new_message);
+        }
+        if !room.encrypted && !hidden {
+            room.notify_bots(BotEvent::Typing {
+                name: person_name.clone(),
+                content: new_message,
+            });
         }
+        room.last_typed.insert(person_name.clone(), Instant::now());
+        room.dirty_typists.insert(person_name);
     }
     StatusCode::OK.into_response()
 }
 
+pub async fn set_typing_visibility(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(RoomParams { room_id }): Path<RoomParams>,
+    Json(payload): Json<TypingVisibilityRequest>,
+) -> impl IntoResponse {
+    let Some(connection_id) = get_connection_cookie(&headers) else {
+        return StatusCode::UNAUTHORIZED;
+    };
+
+    let mut rooms = state.rooms.lock().await;
+    let Some(room) = rooms.get_mut(&room_id) else {
+        return StatusCode::NOT_FOUND;
+    };
+    let Some(name) = room.id_to_name.get(&connection_id).cloned() else {
+        return StatusCode::FORBIDDEN;
+    };
+
+    if payload.hide_typing {
+        room.hidden_typists.insert(connection_id.clone());
+        // whatever was already drafted shouldn't end up in a replay either
+        room.recorder.discard(&name);
+    } else {
+        room.hidden_typists.remove(&connection_id);
+    }
+
+    // re-render the draft straight away so the switch takes effect before the next keystroke
+    let hidden = room.drafts_hidden(&connection_id);
+    if let Some(message) = room.typing_state.get_mut(&name) {
+        message.hidden = hidden;
+        // neither the text from before hiding nor the placeholder is worth keeping, the client resends
+        if !message.content.is_empty() {
+            message.content = if hidden { HIDDEN_DRAFT.to_string() } else { String::new() };
+        }
+        room.typing_seqs.remove(&name);
+        room.dirty_typists.insert(name);
+    }
+    StatusCode::OK
+}
+
 pub async fn submit_message(
     headers: HeaderMap,
     State(state): State<Arc<AllRooms>>,
@@ -465,6 +2304,10 @@ pub async fn submit_message(
 
     let mut rooms = state.rooms.lock().await;
     if let Some(room) = rooms.get_mut(&room_id) {
+        if room.is_spectator(&connection_id) {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+
         let person_name = match room.id_to_name.get(&connection_id).cloned() {
             Some(name) => name,
             None => {
@@ -479,28 +2322,16 @@ pub async fn submit_message(
         };
 
         let mut new_message = payload.message.clone();
-        if payload.message.len() > MAX_MESSAGE_SIZE {
+        if room.encrypted {
+            if payload.message.len() > MAX_ENCRYPTED_MESSAGE_SIZE {
+                return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+            }
+        } else if payload.message.len() > MAX_MESSAGE_SIZE {
             new_message = "This message was too long! Keep it under 4,000 characters".to_string();
         }
 
-        room.message_history.push(Message{
-            name: person_name.clone(),
-            content: new_message,
-            color: name_to_color(&person_name),
-            connection_id: connection_id.clone(),
-        });
-        room.typing_state.insert(String::from(person_name.clone()), Message{
-            name: person_name.clone(),
-            content: String::from(""),
-            color: name_to_color(&person_name),
-            connection_id: connection_id.clone(),
-        });
-        if let Err(e) = room.tx.send(ActionEvent {
-            connection_id: connection_id.clone(),
-            action: Action::Send,
-        }) {
-            println!("Error broadcasting: {}", e);
-        }
+        let is_owner = state.is_owner(&headers, &room_id, room);
+        room.accept_message(&connection_id, person_name, new_message, is_owner);
     }
     StatusCode::OK.into_response()
 }
@@ -524,46 +2355,481 @@ pub async fn set_name(
 
     let mut rooms = state.rooms.lock().await;
     if let Some(room) = rooms.get_mut(&room_id) {
-        if !room.name_to_id.contains_key(&payload.name) {
+        if room.is_spectator(&connection_id) {
+            return StatusCode::FORBIDDEN.into_response();
+        }
 
-            // set name if it's available
-            room.name_to_id.insert(payload.name.clone(), connection_id.clone());
-            room.id_to_name.insert(connection_id.clone(), payload.name.clone());
-            room.name_to_color.insert(payload.name.clone(), name_to_color(&payload.name));
-
-            room.typing_state.insert(String::from(payload.name.clone()), Message {
-                name: payload.name.clone(),
-                content: "".to_string(),
-                color: name_to_color(&payload.name),
-                connection_id: connection_id.clone(),
-            });
+        // same limits as /nick, checked before an invite gets used up
+        let name = payload.name.trim().to_string();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            let template = SetNameTemplate {
+                room_id,
+                message: "Names need 1 to 32 characters".to_string(),
+            }.render().unwrap();
 
-            if let Err(e) = room.tx.send(ActionEvent {
-                connection_id: connection_id.clone(),
-                action: Action::SetName,
-            }) {
-                println!("Error broadcasting name change: {}", e);
-            }
-            return (StatusCode::OK, "").into_response();
-        } else {
+            return (
+                StatusCode::OK,
+                [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+                format!("event: datastar-merge-fragments\ndata: {}\n\n", template)
+            ).into_response();
+        }
+
+        let is_owner = state.is_owner(&headers, &room_id, room);
+        if !room.admit(&connection_id, is_owner, Some(&payload.invite)) {
+            let template = SetNameTemplate {
+                room_id,
+                message: "Your invite is no longer valid".to_string(),
+            }.render().unwrap();
+
+            return (
+                StatusCode::OK,
+                [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+                format!("event: datastar-merge-fragments\ndata: {}\n\n", template)
+            ).into_response();
+        }
+
+        // one seat per connection, renaming after that goes through /nick
+        if room.id_to_name.contains_key(&connection_id) {
+            return StatusCode::CONFLICT.into_response();
+        }
+
+        if room.name_taken(&name) {
             // name already taken
             let template = SetNameTemplate {
                 room_id,
                 message: "Name already taken".to_string(),
             }.render().unwrap();
 
-            return (
+            (
                 StatusCode::OK,
                 [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
                 format!("event: datastar-merge-fragments\ndata: fragments {}\n\n", template)
-            ).into_response();
+            ).into_response()
+        } else if room.is_full() {
+            // no seats left, wait in line and watch until one frees up, keeping your place on a resubmit
+            match room.waiting_line.iter_mut().find(|(id, _)| *id == connection_id) {
+                Some((_, waiting_name)) => *waiting_name = name,
+                None => room.waiting_line.push_back((connection_id.clone(), name)),
+            }
+            if let Err(e) = room.tx.send(ActionEvent {
+                connection_id: connection_id.clone(),
+                action: Action::WaitingLine,
+            }) {
+                println!("Error broadcasting waiting line: {}", e);
+            }
+            (StatusCode::OK, "").into_response()
+        } else {
+            // set name if it's available
+            room.seat_participant(&connection_id, &name);
+            (StatusCode::OK, "").into_response()
         }
     } else {
         // room not found
-        return (
+        (
             StatusCode::OK,
             [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
             "event: datastar-merge-fragments\ndata: fragments <div class='error-message'>Room not found</div>\n\n"
-        ).into_response();
+        ).into_response()
+    }
+}
+
+// for scripts and CI, authenticated with the room's post token instead of a browser session
+pub async fn post_message(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(RoomParams { room_id }): Path<RoomParams>,
+    Json(payload): Json<PostMessageRequest>,
+) -> (StatusCode, &'static str) {
+    let token = headers.get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+
+    let mut rooms = state.rooms.lock().await;
+    let Some(room) = rooms.get_mut(&room_id) else {
+        return (StatusCode::NOT_FOUND, "Room not found");
+    };
+    if room.encrypted {
+        return (StatusCode::CONFLICT, "Encrypted rooms can't take posted messages");
+    }
+    let authorized = token.is_some_and(|token| verify_signature(
+        &state.secret,
+        &format!("post:{}:{}", room_id, room.creation_nonce),
+        token,
+    ));
+    if !authorized {
+        return (StatusCode::UNAUTHORIZED, "Send the room's post token as a bearer token");
+    }
+
+    let name = payload.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return (StatusCode::BAD_REQUEST, "Names need 1 to 32 characters");
+    }
+    if room.name_taken(name) {
+        return (StatusCode::CONFLICT, "Someone in the room already has that name");
+    }
+    if payload.message.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "The message is empty");
+    }
+    if payload.message.len() > MAX_MESSAGE_SIZE {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "Keep messages under 4,000 characters");
+    }
+
+    room.post_incoming_message(name, payload.message);
+    (StatusCode::CREATED, "Posted")
+}
+
+pub async fn extend_room(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(RoomParams { room_id }): Path<RoomParams>,
+    Json(payload): Json<ExtendRequest>,
+) -> StatusCode {
+    let mut rooms = state.rooms.lock().await;
+    match rooms.get_mut(&room_id) {
+        Some(room) if state.is_owner(&headers, &room_id, room) => {
+            room.extend(Duration::from_secs(payload.minutes.clamp(1, 60) * 60));
+            StatusCode::OK
+        },
+        Some(_) => StatusCode::FORBIDDEN,
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+pub async fn set_room_topic(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(RoomParams { room_id }): Path<RoomParams>,
+    Json(payload): Json<TopicRequest>,
+) -> StatusCode {
+    let mut rooms = state.rooms.lock().await;
+    match rooms.get_mut(&room_id) {
+        Some(room) if state.is_owner(&headers, &room_id, room) => {
+            room.set_topic(&payload.topic);
+            let announcement = match room.topic() {
+                Some(topic) => format!("The owner set the topic: {}", topic),
+                None => "The owner cleared the topic".to_string(),
+            };
+            room.post_system_message(announcement);
+            StatusCode::OK
+        },
+        Some(_) => StatusCode::FORBIDDEN,
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+pub async fn pin_message(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(PinParams { room_id, message_id }): Path<PinParams>,
+) -> StatusCode {
+    let mut rooms = state.rooms.lock().await;
+    match rooms.get_mut(&room_id) {
+        Some(room) if state.is_owner(&headers, &room_id, room) => {
+            room.pin(message_id);
+            StatusCode::OK
+        },
+        Some(_) => StatusCode::FORBIDDEN,
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+pub async fn unpin_message(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(PinParams { room_id, message_id }): Path<PinParams>,
+) -> StatusCode {
+    let mut rooms = state.rooms.lock().await;
+    match rooms.get_mut(&room_id) {
+        Some(room) if state.is_owner(&headers, &room_id, room) => {
+            room.unpin(message_id);
+            StatusCode::OK
+        },
+        Some(_) => StatusCode::FORBIDDEN,
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+pub async fn set_alerts(Json(prefs): Json<AlertPrefs>) -> impl IntoResponse {
+    (StatusCode::OK, [(SET_COOKIE, prefs.cookie())])
+}
+
+pub async fn create_poll(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(RoomParams { room_id }): Path<RoomParams>,
+    Json(payload): Json<PollRequest>,
+) -> Response<Body> {
+    let Some(connection_id) = get_connection_cookie(&headers) else {
+        return StatusCode::FORBIDDEN.into_response();
+    };
+    let mut rooms = state.rooms.lock().await;
+    let Some(room) = rooms.get_mut(&room_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // the question would go out in the clear
+    if room.encrypted {
+        return StatusCode::CONFLICT.into_response();
+    }
+    let Some(name) = room.id_to_name.get(&connection_id).cloned() else {
+        return StatusCode::FORBIDDEN.into_response();
+    };
+
+    let options: Vec<&str> = payload.options.lines().collect();
+    match room.start_poll(&connection_id, &name, &payload.question, &options) {
+        Ok(()) => (
+            StatusCode::OK,
+            [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+            "event: datastar-merge-signals\ndata: signals {pollQuestion: '', pollOptions: ''}\n\n",
+        ).into_response(),
+        Err(reason) => {
+            let _ = room.tx.send(ActionEvent {
+                connection_id,
+                action: Action::Notice(vec![reason.to_string()]),
+            });
+            StatusCode::OK.into_response()
+        },
+    }
+}
+
+pub async fn vote_in_poll(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(VoteParams { room_id, poll_id, option }): Path<VoteParams>,
+) -> StatusCode {
+    let Some(connection_id) = get_connection_cookie(&headers) else {
+        return StatusCode::FORBIDDEN;
+    };
+    let mut rooms = state.rooms.lock().await;
+    let Some(room) = rooms.get_mut(&room_id) else {
+        return StatusCode::NOT_FOUND;
+    };
+    if !room.id_to_name.contains_key(&connection_id) || room.is_spectator(&connection_id) {
+        return StatusCode::FORBIDDEN;
+    }
+    if let Err(reason) = room.vote(poll_id, &connection_id, option) {
+        let _ = room.tx.send(ActionEvent {
+            connection_id,
+            action: Action::Notice(vec![reason.to_string()]),
+        });
+    }
+    StatusCode::OK
+}
+
+pub async fn close_poll(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(PollParams { room_id, poll_id }): Path<PollParams>,
+) -> StatusCode {
+    // the owner doesn't need to have joined to close a poll
+    let connection_id = get_connection_cookie(&headers).unwrap_or_default();
+    let mut rooms = state.rooms.lock().await;
+    let Some(room) = rooms.get_mut(&room_id) else {
+        return StatusCode::NOT_FOUND;
+    };
+    let is_owner = state.is_owner(&headers, &room_id, room);
+    match room.close_poll(poll_id, &connection_id, is_owner) {
+        Ok(()) => StatusCode::OK,
+        Err(_) => StatusCode::FORBIDDEN,
+    }
+}
+
+pub async fn list_invites(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(RoomParams { room_id }): Path<RoomParams>,
+) -> Response<Body> {
+    let rooms = state.rooms.lock().await;
+    match rooms.get(&room_id) {
+        Some(room) if state.is_owner(&headers, &room_id, room) => invite_list_response(&room_id, room),
+        Some(_) => StatusCode::FORBIDDEN.into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub async fn create_invite(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(RoomParams { room_id }): Path<RoomParams>,
+    Json(payload): Json<CreateInviteRequest>,
+) -> Response<Body> {
+    let mut rooms = state.rooms.lock().await;
+    match rooms.get_mut(&room_id) {
+        Some(room) if state.is_owner(&headers, &room_id, room) => {
+            room.invites.mint(
+                payload.max_uses.try_into().unwrap_or(u32::MAX),
+                payload.minutes,
+            );
+            invite_list_response(&room_id, room)
+        },
+        Some(_) => StatusCode::FORBIDDEN.into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub async fn revoke_invite(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(InviteTokenParams { room_id, token }): Path<InviteTokenParams>,
+) -> Response<Body> {
+    let mut rooms = state.rooms.lock().await;
+    match rooms.get_mut(&room_id) {
+        Some(room) if state.is_owner(&headers, &room_id, room) => {
+            // people who already joined with it stay, it just stops letting new people in
+            room.invites.revoke(&token);
+            invite_list_response(&room_id, room)
+        },
+        Some(_) => StatusCode::FORBIDDEN.into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub async fn search_room(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(RoomParams { room_id }): Path<RoomParams>,
+    Json(payload): Json<SearchRequest>,
+) -> Response<Body> {
+    let connection_id = get_connection_cookie(&headers)
+        .expect("Middleware should have bestowed UUID by now.");
+
+    let rooms = state.rooms.lock().await;
+    match rooms.get(&room_id) {
+        Some(room) => search_response(room, &connection_id, &payload),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub async fn watch_search_room(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(SpectatorParams { spectator_key }): Path<SpectatorParams>,
+    Json(payload): Json<SearchRequest>,
+) -> Response<Body> {
+    let connection_id = get_connection_cookie(&headers)
+        .expect("Middleware should have bestowed UUID by now.");
+
+    let rooms = state.rooms.lock().await;
+    match room_id_for_spectator_key(&rooms, &spectator_key) {
+        Some(room_id) => search_response(&rooms[&room_id], &connection_id, &payload),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+fn search_response(room: &Room, connection_id: &str, search: &SearchRequest) -> Response<Body> {
+    // same rule as replays, you have to be looking at the room already
+    if !room.is_connected(connection_id) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let text = search.text.trim().to_lowercase();
+    let author = search.author.trim().to_lowercase();
+
+    // the server only holds ciphertext for encrypted rooms, nothing to match against
+    let searched = !room.encrypted && (!text.is_empty() || !author.is_empty());
+    let matches: Vec<&Message> = if searched {
+        room.message_history.iter()
+            .filter(|message| author.is_empty() || message.name.to_lowercase().contains(&author))
+            .filter(|message| text.is_empty() || message.content.to_lowercase().contains(&text))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // newest first, that's usually what people are after
+    let results = matches.iter()
+        .rev()
+        .take(MAX_SEARCH_RESULTS)
+        .map(|message| Message {
+            content: match message.content.char_indices().nth(SEARCH_SNIPPET_LENGTH) {
+                Some((cut, _)) => format!("{}…", &message.content[..cut]),
+                None => message.content.clone(),
+            },
+            mentions: Vec::new(),
+            ..(*message).clone()
+        })
+        .collect();
+
+    let rendered = SearchResultsTemplate {
+        searched,
+        encrypted: room.encrypted,
+        total: matches.len(),
+        results,
+    }.render().unwrap();
+
+    (
+        StatusCode::OK,
+        [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+        create_fragments_response(&rendered),
+    ).into_response()
+}
+
+pub async fn export_room(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(ExportParams { room_id, format }): Path<ExportParams>,
+) -> Response<Body> {
+    let connection_id = get_connection_cookie(&headers)
+        .expect("Middleware should have bestowed UUID by now.");
+
+    let rooms = state.rooms.lock().await;
+    match rooms.get(&room_id) {
+        Some(room) => export_response(Some(&room_id), &room_id, room, &connection_id, &format),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub async fn watch_export_room(
+    headers: HeaderMap,
+    State(state): State<Arc<AllRooms>>,
+    Path(SpectatorExportParams { spectator_key, format }): Path<SpectatorExportParams>,
+) -> Response<Body> {
+    let connection_id = get_connection_cookie(&headers)
+        .expect("Middleware should have bestowed UUID by now.");
+
+    let rooms = state.rooms.lock().await;
+    match room_id_for_spectator_key(&rooms, &spectator_key) {
+        Some(room_id) => export_response(None, &spectator_key, &rooms[&room_id], &connection_id, &format),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+// spectators only ever see their key, never the room id
+fn export_response(room_id: Option<&str>, file_stem: &str, room: &Room, connection_id: &str, format: &str) -> Response<Body> {
+    let Some(format) = ExportFormat::from_extension(format) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !room.can_export() {
+        return (StatusCode::FORBIDDEN, "Exports are turned off for this room").into_response();
+    }
+    if !room.is_connected(connection_id) {
+        return StatusCode::FORBIDDEN.into_response();
     }
+
+    let transcript = Transcript::new(room_id, &room.title, &room.message_history);
+    (
+        StatusCode::OK,
+        [
+            (axum::http::header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                axum::http::header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}-transcript.{}\"", file_stem, format.extension()),
+            ),
+        ],
+        transcript.render(format),
+    ).into_response()
+}
+
+fn invite_list_response(room_id: &str, room: &Room) -> Response<Body> {
+    let rendered = InviteListTemplate {
+        room_id: room_id.to_string(),
+        invite_only: room.invite_only,
+        invites: room.invites.views(),
+    }.render().unwrap();
+
+    (
+        StatusCode::OK,
+        [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+        create_fragments_response(&rendered),
+    ).into_response()
 }
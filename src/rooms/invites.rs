@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use super::utils::{
+    format_time,
+    random_token,
+};
+
+const INVITE_TOKEN_LENGTH: usize = 20;
+const MAX_INVITE_USES: u32 = 100;
+const MAX_INVITE_MINUTES: u64 = 12 * 60;
+
+#[derive(Clone, Debug)]
+pub struct Invite {
+    pub token: String,
+    pub uses: u32,
+    pub max_uses: u32,
+    pub expiration: Instant,
+}
+
+impl Invite {
+    fn is_usable(&self) -> bool {
+        self.uses < self.max_uses && Instant::now() < self.expiration
+    }
+
+    fn status(&self) -> String {
+        if self.uses >= self.max_uses {
+            "used up".to_string()
+        } else if Instant::now() >= self.expiration {
+            "expired".to_string()
+        } else {
+            format_time(self.expiration.duration_since(Instant::now()))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct InviteView {
+    pub token: String,
+    pub uses: u32,
+    pub max_uses: u32,
+    pub status: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Invites {
+    invites: HashMap<String, Invite>,
+}
+
+impl Invites {
+    pub fn mint(&mut self, max_uses: u32, minutes: u64) -> String {
+        let token = random_token(INVITE_TOKEN_LENGTH);
+        self.invites.insert(token.clone(), Invite {
+            token: token.clone(),
+            uses: 0,
+            max_uses: max_uses.clamp(1, MAX_INVITE_USES),
+            expiration: Instant::now() + Duration::from_secs(minutes.clamp(1, MAX_INVITE_MINUTES) * 60),
+        });
+        token
+    }
+
+    pub fn is_valid(&self, token: &str) -> bool {
+        self.invites.get(token).is_some_and(|invite| invite.is_usable())
+    }
+
+    /// Uses up one join from the invite, false if it can't be used anymore.
+    pub fn redeem(&mut self, token: &str) -> bool {
+        match self.invites.get_mut(token) {
+            Some(invite) if invite.is_usable() => {
+                invite.uses += 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn revoke(&mut self, token: &str) -> bool {
+        self.invites.remove(token).is_some()
+    }
+
+    pub fn views(&self) -> Vec<InviteView> {
+        let mut invites: Vec<&Invite> = self.invites.values().collect();
+        invites.sort_by_key(|invite| invite.expiration);
+        invites.into_iter()
+            .map(|invite| InviteView {
+                token: invite.token.clone(),
+                uses: invite.uses,
+                max_uses: invite.max_uses,
+                status: invite.status(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,614 @@
+//! Optional IRC listener, so classic clients can sit in rooms.
+//!
+//! `#room-id` is the room, the nick is the name people see and PRIVMSG goes through the same
+//! path as the browser's submit. Drafts are never sent as text: clients that ask for the IRCv3
+//! `message-tags` capability get `+typing` TAGMSGs, everyone else doesn't see typing at all.
+
+use std::{collections::HashMap, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use uuid::Uuid;
+
+use super::gateway::{GatewayEvent, GatewaySeat, JoinRefused, TypingState};
+use super::handlers::{
+    AllRooms,
+    MessageKind,
+    MAX_NAME_LENGTH,
+};
+
+const SERVER_NAME: &str = "impermachat";
+// the IRCv3 limit for a line including tags
+const MAX_LINE_LENGTH: u64 = 8191;
+const MAX_CHANNELS: usize = 10;
+
+pub async fn serve(state: Arc<AllRooms>, address: String) {
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Couldn't start the IRC listener on {}: {}", address, e);
+            return;
+        },
+    };
+    println!("IRC listening on {}", address);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_client(state.clone(), stream));
+            },
+            Err(e) => println!("Error accepting IRC connection: {}", e),
+        }
+    }
+}
+
+struct Channel {
+    room_id: String,
+    relay: JoinHandle<()>,
+}
+
+struct Client {
+    state: Arc<AllRooms>,
+    // stands in for the browser's impermachat_id cookie
+    connection_id: String,
+    nick: Option<String>,
+    has_user: bool,
+    registered: bool,
+    // registration waits for CAP END once a client starts negotiating
+    negotiating: bool,
+    message_tags: bool,
+    channels: HashMap<String, Channel>,
+    out: mpsc::UnboundedSender<String>,
+    events: mpsc::UnboundedSender<(String, GatewayEvent)>,
+    quit: bool,
+}
+
+async fn handle_client(state: Arc<AllRooms>, stream: TcpStream) {
+    let (read_half, mut write_half) = stream.into_split();
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        while let Some(line) = out_rx.recv().await {
+            if write_half.write_all(format!("{}\r\n", line).as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let mut client = Client {
+        state,
+        connection_id: format!("irc-{}", Uuid::new_v4().simple()),
+        nick: None,
+        has_user: false,
+        registered: false,
+        negotiating: false,
+        message_tags: false,
+        channels: HashMap::new(),
+        out: out_tx,
+        events: events_tx,
+        quit: false,
+    };
+
+    let mut reader = BufReader::new(read_half);
+    let mut buffer = Vec::new();
+    while !client.quit {
+        // a partial line survives select! picking the other branch, so the limit counts it too
+        let mut limited = (&mut reader).take((MAX_LINE_LENGTH + 1).saturating_sub(buffer.len() as u64));
+        tokio::select! {
+            read = limited.read_until(b'\n', &mut buffer) => {
+                match read {
+                    _ if !buffer.ends_with(b"\n") && buffer.len() as u64 > MAX_LINE_LENGTH => {
+                        client.send_error("Line too long");
+                        break;
+                    },
+                    Ok(0) | Err(_) => break,
+                    Ok(_) if !buffer.ends_with(b"\n") => {},
+                    Ok(_) => {
+                        let line = String::from_utf8_lossy(&buffer).trim_end_matches(['\r', '\n']).to_string();
+                        buffer.clear();
+                        client.handle_line(&line).await;
+                    },
+                }
+            },
+            Some((channel, event)) = events_rx.recv() => client.relay(&channel, event).await,
+        }
+    }
+
+    for channel in client.channels.keys().cloned().collect::<Vec<_>>() {
+        client.leave(&channel).await;
+    }
+}
+
+/// A parsed client line, tags are dropped since nothing the gateway accepts uses them.
+struct Command<'a> {
+    verb: String,
+    params: Vec<&'a str>,
+}
+
+fn parse(line: &str) -> Option<Command<'_>> {
+    let mut rest = line.trim_start();
+    if rest.starts_with('@') {
+        rest = rest.split_once(' ')?.1.trim_start();
+    }
+    if rest.starts_with(':') {
+        rest = rest.split_once(' ')?.1.trim_start();
+    }
+
+    let (verb, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    if verb.is_empty() {
+        return None;
+    }
+    let mut params = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(' ');
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(trailing) = rest.strip_prefix(':') {
+            params.push(trailing);
+            break;
+        }
+        let (param, next) = rest.split_once(' ').unwrap_or((rest, ""));
+        params.push(param);
+        rest = next;
+    }
+    Some(Command { verb: verb.to_ascii_uppercase(), params })
+}
+
+// room names can have spaces and other characters IRC uses as separators
+fn irc_nick(name: &str) -> String {
+    let nick: String = name.chars()
+        .map(|c| if c.is_whitespace() || matches!(c, '!' | '@' | ',' | '*' | '?' | ':' | '\0') { '_' } else { c })
+        .collect();
+    if nick.is_empty() || nick.starts_with(['#', '&']) {
+        format!("_{}", nick)
+    } else {
+        nick
+    }
+}
+
+// room text goes out inside IRC lines, where \r or \n would start a new line and \0 isn't allowed
+fn irc_text(text: &str) -> String {
+    text.chars().filter(|c| !matches!(c, '\r' | '\n' | '\0')).collect()
+}
+
+fn valid_nick(nick: &str) -> bool {
+    !nick.is_empty()
+        && nick.chars().count() <= MAX_NAME_LENGTH
+        && irc_nick(nick) == nick
+}
+
+fn room_id(channel: &str) -> Option<&str> {
+    channel.strip_prefix('#').filter(|room_id| !room_id.is_empty())
+}
+
+impl Client {
+    fn nick(&self) -> &str {
+        self.nick.as_deref().unwrap_or("*")
+    }
+
+    fn send(&self, line: String) {
+        let _ = self.out.send(line);
+    }
+
+    fn numeric(&self, code: &str, params: &str) {
+        self.send(format!(":{} {} {} {}", SERVER_NAME, code, self.nick(), params));
+    }
+
+    fn send_error(&self, reason: &str) {
+        self.send(format!("ERROR :{}", reason));
+    }
+
+    fn prefix(name: &str) -> String {
+        let nick = irc_nick(name);
+        format!("{}!{}@{}", nick, nick, SERVER_NAME)
+    }
+
+    async fn handle_line(&mut self, line: &str) {
+        let Some(command) = parse(line) else {
+            return;
+        };
+        let params = command.params;
+        match command.verb.as_str() {
+            "CAP" => self.cap(&params),
+            "NICK" => self.set_nick(params.first().copied().unwrap_or("")).await,
+            "USER" => {
+                if self.registered {
+                    self.numeric("462", ":You may not reregister");
+                } else if params.len() < 4 {
+                    self.numeric("461", "USER :Not enough parameters");
+                } else {
+                    self.has_user = true;
+                    self.try_register();
+                }
+            },
+            "PING" => self.send(format!(":{} PONG {} :{}", SERVER_NAME, SERVER_NAME, params.first().unwrap_or(&""))),
+            "PONG" => {},
+            "QUIT" => {
+                self.send_error("Bye");
+                self.quit = true;
+            },
+            _ if !self.registered => self.numeric("451", ":You have not registered"),
+            "JOIN" => {
+                let channels = params.first().copied().unwrap_or("");
+                if channels == "0" {
+                    for channel in self.channels.keys().cloned().collect::<Vec<_>>() {
+                        self.part(&channel, "Leaving").await;
+                    }
+                }
+                for channel in channels.split(',').filter(|channel| !channel.is_empty() && *channel != "0") {
+                    self.join(&channel.to_ascii_lowercase()).await;
+                }
+            },
+            "PART" => {
+                let reason = params.get(1).copied().unwrap_or("Leaving");
+                for channel in params.first().copied().unwrap_or("").split(',') {
+                    let channel = channel.to_ascii_lowercase();
+                    if self.channels.contains_key(&channel) {
+                        self.part(&channel, reason).await;
+                    } else {
+                        self.numeric("442", &format!("{} :You're not on that channel", channel));
+                    }
+                }
+            },
+            "PRIVMSG" | "NOTICE" => {
+                let (Some(target), Some(text)) = (params.first(), params.get(1)) else {
+                    self.numeric("411", ":No recipient or text given");
+                    return;
+                };
+                // notices must never trigger replies, so they're dropped rather than refused
+                if command.verb == "NOTICE" {
+                    return;
+                }
+                self.privmsg(&target.to_ascii_lowercase(), text).await;
+            },
+            "TAGMSG" => {},
+            "TOPIC" => {
+                let Some(channel) = params.first().map(|channel| channel.to_ascii_lowercase()) else {
+                    self.numeric("461", "TOPIC :Not enough parameters");
+                    return;
+                };
+                if !self.channels.contains_key(&channel) {
+                    self.numeric("442", &format!("{} :You're not on that channel", channel));
+                } else if params.len() > 1 {
+                    self.numeric("482", &format!("{} :Only the room's owner can set the topic, from the browser", channel));
+                } else {
+                    self.send_topic(&channel).await;
+                }
+            },
+            "NAMES" => {
+                for channel in params.first().copied().unwrap_or("").split(',').filter(|channel| !channel.is_empty()) {
+                    self.send_names(&channel.to_ascii_lowercase()).await;
+                }
+            },
+            "MODE" => match params.first() {
+                Some(target) if target.starts_with('#') => self.numeric("324", &format!("{} +nt", target)),
+                Some(_) => self.numeric("221", "+i"),
+                None => self.numeric("461", "MODE :Not enough parameters"),
+            },
+            "WHO" => self.numeric("315", &format!("{} :End of WHO list", params.first().unwrap_or(&"*"))),
+            "LIST" => {
+                // rooms are unlisted, knowing the name is what gets you in
+                self.numeric("321", "Channel :Users Name");
+                self.numeric("323", ":End of LIST");
+            },
+            verb => self.numeric("421", &format!("{} :Unknown command", verb)),
+        }
+    }
+
+    fn cap(&mut self, params: &[&str]) {
+        let nick = self.nick().to_string();
+        match params.first().map(|subcommand| subcommand.to_ascii_uppercase()).as_deref() {
+            Some("LS") => {
+                self.negotiating = true;
+                self.send(format!(":{} CAP {} LS :message-tags", SERVER_NAME, nick));
+            },
+            Some("LIST") => {
+                let enabled = if self.message_tags { "message-tags" } else { "" };
+                self.send(format!(":{} CAP {} LIST :{}", SERVER_NAME, nick, enabled));
+            },
+            Some("REQ") => {
+                self.negotiating = true;
+                let requested = params.get(1).copied().unwrap_or("");
+                let mut enable = None;
+                let supported = requested.split_whitespace().all(|capability| match capability {
+                    "message-tags" => {
+                        enable = Some(true);
+                        true
+                    },
+                    "-message-tags" => {
+                        enable = Some(false);
+                        true
+                    },
+                    _ => false,
+                });
+                if supported {
+                    if let Some(enable) = enable {
+                        self.message_tags = enable;
+                    }
+                    self.send(format!(":{} CAP {} ACK :{}", SERVER_NAME, nick, requested));
+                } else {
+                    self.send(format!(":{} CAP {} NAK :{}", SERVER_NAME, nick, requested));
+                }
+            },
+            Some("END") => {
+                self.negotiating = false;
+                self.try_register();
+            },
+            _ => self.numeric("410", &format!("{} :Invalid CAP command", params.first().unwrap_or(&""))),
+        }
+    }
+
+    fn try_register(&mut self) {
+        if self.registered || self.negotiating || !self.has_user || self.nick.is_none() {
+            return;
+        }
+        self.registered = true;
+        self.numeric("001", &format!(":Welcome to Impermachat, {}", self.nick()));
+        self.numeric("002", &format!(":Your host is {}", SERVER_NAME));
+        self.numeric("003", ":Rooms come and go, this server keeps nothing");
+        self.numeric("004", &format!("{} {} i nt", SERVER_NAME, env!("CARGO_PKG_VERSION")));
+        self.numeric("005", &format!("CHANTYPES=# NICKLEN={} CHANLIMIT=#:{} :are supported by this server", MAX_NAME_LENGTH, MAX_CHANNELS));
+        self.numeric("422", ":Join a room with /join #room-name");
+    }
+
+    async fn set_nick(&mut self, nick: &str) {
+        if nick.is_empty() {
+            self.numeric("431", ":No nickname given");
+            return;
+        }
+        if !valid_nick(nick) {
+            self.numeric("432", &format!("{} :Erroneous nickname", nick));
+            return;
+        }
+        if self.nick.as_deref() == Some(nick) {
+            return;
+        }
+
+        // the nick is this client's name in every room it's in, so all of them have to agree
+        let mut renamed = Vec::new();
+        for channel in self.channels.values() {
+            if let Err(reason) = self.state.gateway_rename(&channel.room_id, &self.connection_id, nick).await {
+                for room_id in renamed {
+                    let _ = self.state.gateway_rename(room_id, &self.connection_id, self.nick()).await;
+                }
+                self.numeric("433", &format!("{} :{}", nick, reason));
+                return;
+            }
+            renamed.push(&channel.room_id);
+        }
+
+        if self.registered {
+            self.send(format!(":{} NICK {}", Self::prefix(self.nick()), nick));
+        }
+        self.nick = Some(nick.to_string());
+        self.try_register();
+    }
+
+    async fn join(&mut self, channel: &str) {
+        if self.channels.contains_key(channel) {
+            return;
+        }
+        let Some(room_id) = room_id(channel) else {
+            self.numeric("403", &format!("{} :Channels are #room-name", channel));
+            return;
+        };
+        if self.channels.len() >= MAX_CHANNELS {
+            self.numeric("405", &format!("{} :You have joined too many rooms", channel));
+            return;
+        }
+
+        let seat = match self.state.gateway_join(room_id, &self.connection_id, self.nick()).await {
+            Ok(seat) => seat,
+            Err(refused) => {
+                let code = match refused {
+                    JoinRefused::NotFound | JoinRefused::Encrypted => "403",
+                    JoinRefused::InviteOnly => "473",
+                    JoinRefused::NameTaken => "437",
+                    JoinRefused::Full => "471",
+                    JoinRefused::Kicked => "474",
+                };
+                self.numeric(code, &format!("{} :{}", channel, refused.message()));
+                return;
+            },
+        };
+
+        self.send(format!(":{} JOIN {}", Self::prefix(self.nick()), channel));
+        match &seat.topic {
+            Some(topic) => self.numeric("332", &format!("{} :{}", channel, irc_text(topic))),
+            None => self.numeric("331", &format!("{} :No topic is set", channel)),
+        }
+        let names: Vec<String> = seat.names.iter().map(|name| irc_nick(name)).collect();
+        self.numeric("353", &format!("= {} :{}", channel, names.join(" ")));
+        self.numeric("366", &format!("{} :End of NAMES list", channel));
+        for message in &seat.history {
+            self.relay_message(channel, &message.name, &message.content, message.kind);
+        }
+
+        let relay = tokio::spawn(relay_room(self.state.clone(), seat, channel.to_string(), self.events.clone()));
+        self.channels.insert(channel.to_string(), Channel {
+            room_id: room_id.to_string(),
+            relay,
+        });
+    }
+
+    async fn part(&mut self, channel: &str, reason: &str) {
+        self.send(format!(":{} PART {} :{}", Self::prefix(self.nick()), channel, reason));
+        self.leave(channel).await;
+    }
+
+    async fn leave(&mut self, channel: &str) {
+        if let Some(joined) = self.channels.remove(channel) {
+            joined.relay.abort();
+            self.state.gateway_leave(&joined.room_id, &self.connection_id).await;
+        }
+    }
+
+    async fn privmsg(&mut self, target: &str, text: &str) {
+        let Some(channel) = self.channels.get(target) else {
+            if target.starts_with('#') {
+                self.numeric("442", &format!("{} :You're not on that channel", target));
+            } else {
+                self.numeric("401", &format!("{} :Rooms don't have private messages", target));
+            }
+            return;
+        };
+        if let Some(action) = text.strip_prefix("\x01ACTION ").map(|action| action.trim_end_matches('\x01')) {
+            self.state.gateway_emote(&channel.room_id, &self.connection_id, action).await;
+        } else if !text.starts_with('\x01') && !text.trim().is_empty() {
+            self.state.gateway_say(&channel.room_id, &self.connection_id, text).await;
+        }
+    }
+
+    async fn send_topic(&self, channel: &str) {
+        let Some(joined) = self.channels.get(channel) else {
+            return;
+        };
+        match self.state.room_topic(&joined.room_id).await {
+            Some(topic) => self.numeric("332", &format!("{} :{}", channel, irc_text(&topic))),
+            None => self.numeric("331", &format!("{} :No topic is set", channel)),
+        }
+    }
+
+    async fn send_names(&self, channel: &str) {
+        if let Some(joined) = self.channels.get(channel) {
+            let names: Vec<String> = self.state.room_names(&joined.room_id).await
+                .iter()
+                .map(|name| irc_nick(name))
+                .collect();
+            self.numeric("353", &format!("= {} :{}", channel, names.join(" ")));
+        }
+        self.numeric("366", &format!("{} :End of NAMES list", channel));
+    }
+
+    fn relay_message(&self, channel: &str, name: &str, content: &str, kind: MessageKind) {
+        for line in content.split(['\r', '\n']).map(irc_text).filter(|line| !line.trim().is_empty()) {
+            let line = match kind {
+                MessageKind::System => format!(":{} NOTICE {} :{}", SERVER_NAME, channel, line),
+                MessageKind::Emote => format!(":{} PRIVMSG {} :\x01ACTION {}\x01", Self::prefix(name), channel, line),
+                _ => format!(":{} PRIVMSG {} :{}", Self::prefix(name), channel, line),
+            };
+            self.send(line);
+        }
+    }
+
+    async fn relay(&mut self, channel: &str, event: GatewayEvent) {
+        if !self.channels.contains_key(channel) {
+            return;
+        }
+        match event {
+            GatewayEvent::Message { name, content, kind } => self.relay_message(channel, &name, &content, kind),
+            GatewayEvent::Typing { name, state } => {
+                if self.message_tags {
+                    let state = match state {
+                        TypingState::Active => "active",
+                        TypingState::Paused => "paused",
+                        TypingState::Done => "done",
+                    };
+                    self.send(format!("@+typing={} :{} TAGMSG {}", state, Self::prefix(&name), channel));
+                }
+            },
+            GatewayEvent::Joined(name) => self.send(format!(":{} JOIN {}", Self::prefix(&name), channel)),
+            GatewayEvent::Left(name) => self.send(format!(":{} PART {} :Left the room", Self::prefix(&name), channel)),
+            // NICK is server wide on IRC, a rename in one room reads better as leaving and rejoining
+            GatewayEvent::Renamed { from, to } => {
+                self.send(format!(":{} PART {} :Now known as {}", Self::prefix(&from), channel, irc_nick(&to)));
+                self.send(format!(":{} JOIN {}", Self::prefix(&to), channel));
+            },
+            GatewayEvent::Topic(topic) => {
+                self.send(format!(":{} TOPIC {} :{}", SERVER_NAME, channel, irc_text(&topic.unwrap_or_default())));
+            },
+            GatewayEvent::Notice(lines) => {
+                for line in lines {
+                    self.send(format!(":{} NOTICE {} :{}", SERVER_NAME, channel, irc_text(&line)));
+                }
+            },
+            GatewayEvent::Kicked => {
+                self.send(format!(":{} KICK {} {} :The owner removed you from the room", SERVER_NAME, channel, irc_nick(self.nick())));
+                self.leave(channel).await;
+            },
+            GatewayEvent::Closed(reason) => {
+                self.send(format!(":{} NOTICE {} :{}", SERVER_NAME, channel, reason));
+                self.part(channel, "The room closed").await;
+            },
+        }
+    }
+}
+
+// one per joined room, forwards events until the room is gone or the client leaves it
+async fn relay_room(
+    state: Arc<AllRooms>,
+    mut seat: GatewaySeat,
+    channel: String,
+    events: mpsc::UnboundedSender<(String, GatewayEvent)>,
+) {
+    loop {
+        let event = seat.next(&state).await;
+        let done = matches!(event, GatewayEvent::Closed(_) | GatewayEvent::Kicked);
+        if events.send((channel.clone(), event)).is_err() || done {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn client() -> (Client, mpsc::UnboundedReceiver<String>) {
+        let (out, lines) = mpsc::unbounded_channel();
+        let (events, _) = mpsc::unbounded_channel();
+        let client = Client {
+            state: AllRooms::new(Config::default()),
+            connection_id: "irc-test".to_string(),
+            nick: Some("sam".to_string()),
+            has_user: true,
+            registered: true,
+            negotiating: false,
+            message_tags: false,
+            channels: HashMap::new(),
+            out,
+            events,
+            quit: false,
+        };
+        (client, lines)
+    }
+
+    fn sent(lines: &mut mpsc::UnboundedReceiver<String>) -> Vec<String> {
+        let mut sent = Vec::new();
+        while let Ok(line) = lines.try_recv() {
+            sent.push(line);
+        }
+        sent
+    }
+
+    #[tokio::test]
+    async fn carriage_returns_cant_start_new_lines() {
+        let (client, mut lines) = client();
+        client.relay_message("#room", "alex", "hi\r:evil KICK #room sam\0\r\nbye", MessageKind::Chat);
+        assert_eq!(sent(&mut lines), vec![
+            ":alex!alex@impermachat PRIVMSG #room :hi",
+            ":alex!alex@impermachat PRIVMSG #room ::evil KICK #room sam",
+            ":alex!alex@impermachat PRIVMSG #room :bye",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn topics_and_names_are_kept_on_one_line() {
+        let (mut client, mut lines) = client();
+        client.channels.insert("#room".to_string(), Channel {
+            room_id: "room".to_string(),
+            relay: tokio::spawn(async {}),
+        });
+        client.relay("#room", GatewayEvent::Topic(Some("plans\r\nQUIT".to_string()))).await;
+        client.relay("#room", GatewayEvent::Joined("al\rex\0".to_string())).await;
+        assert_eq!(sent(&mut lines), vec![
+            ":impermachat TOPIC #room :plansQUIT",
+            ":al_ex_!al_ex_@impermachat JOIN #room",
+        ]);
+    }
+}
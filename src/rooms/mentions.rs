@@ -0,0 +1,137 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    ops::Range,
+};
+
+#[derive(Clone, Debug)]
+pub struct Mention {
+    // byte range of the "@name" in the message
+    pub range: Range<usize>,
+    pub connection_id: String,
+}
+
+/// A run of message text, either plain or a resolved mention.
+pub struct Part<'a> {
+    pub text: &'a str,
+    pub mention: bool,
+}
+
+// what can't follow a name for it to still count, so @sam doesn't match in @samantha
+fn continues_name(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Resolves `@name` against everyone seated, case insensitively, preferring the longest name that fits.
+pub fn find(content: &str, name_to_id: &HashMap<String, String>) -> Vec<Mention> {
+    let mut names: Vec<(String, &String)> = name_to_id.iter()
+        .map(|(name, id)| (name.to_lowercase(), id))
+        .collect();
+    names.sort_by_key(|(name, _)| Reverse(name.len()));
+
+    let mut mentions: Vec<Mention> = Vec::new();
+    let mut previous = None;
+    for (index, c) in content.char_indices() {
+        let after_mention = mentions.last().is_some_and(|mention| index < mention.range.end);
+        let starts_word = !previous.is_some_and(continues_name);
+        previous = Some(c);
+        if c != '@' || after_mention || !starts_word {
+            continue;
+        }
+
+        let rest = &content[index + 1..];
+        let found = names.iter().find(|(name, _)| {
+            rest.get(..name.len()).is_some_and(|candidate| candidate.to_lowercase() == *name)
+                && !rest[name.len()..].chars().next().is_some_and(continues_name)
+        });
+        if let Some((name, connection_id)) = found {
+            mentions.push(Mention {
+                range: index..index + 1 + name.len(),
+                connection_id: connection_id.to_string(),
+            });
+        }
+    }
+    mentions
+}
+
+pub fn parts<'a>(content: &'a str, mentions: &[Mention]) -> Vec<Part<'a>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for mention in mentions {
+        if mention.range.start > start {
+            parts.push(Part { text: &content[start..mention.range.start], mention: false });
+        }
+        parts.push(Part { text: &content[mention.range.clone()], mention: true });
+        start = mention.range.end;
+    }
+    if start < content.len() {
+        parts.push(Part { text: &content[start..], mention: false });
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seated(names: &[&str]) -> HashMap<String, String> {
+        names.iter().map(|name| (name.to_string(), format!("{}-id", name))).collect()
+    }
+
+    fn mentioned(content: &str, names: &[&str]) -> Vec<(String, String)> {
+        find(content, &seated(names))
+            .into_iter()
+            .map(|mention| (content[mention.range].to_string(), mention.connection_id))
+            .collect()
+    }
+
+    #[test]
+    fn finds_names_case_insensitively() {
+        assert_eq!(mentioned("hey @SAM, lunch?", &["sam"]), vec![("@SAM".to_string(), "sam-id".to_string())]);
+    }
+
+    #[test]
+    fn needs_a_word_boundary_on_both_sides() {
+        assert!(mentioned("@samantha", &["sam"]).is_empty());
+        assert!(mentioned("mail me at me@sam", &["sam"]).is_empty());
+        assert!(mentioned("@sam_", &["sam"]).is_empty());
+        assert_eq!(mentioned("(@sam)", &["sam"]).len(), 1);
+    }
+
+    #[test]
+    fn prefers_the_longest_name() {
+        assert_eq!(
+            mentioned("@sam smith and @sam", &["sam", "sam smith"]),
+            vec![
+                ("@sam smith".to_string(), "sam smith-id".to_string()),
+                ("@sam".to_string(), "sam-id".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_names_and_handles_multibyte_text() {
+        assert!(mentioned("@nobody here", &["sam"]).is_empty());
+        assert_eq!(mentioned("ça va @zoë?", &["Zoë"]).len(), 1);
+        assert!(mentioned("@", &["sam"]).is_empty());
+    }
+
+    #[test]
+    fn parts_split_around_mentions() {
+        let content = "hi @sam and @al!";
+        let mentions = find(content, &seated(&["sam", "al"]));
+        let parts: Vec<(&str, bool)> = parts(content, &mentions)
+            .iter()
+            .map(|part| (part.text, part.mention))
+            .collect();
+        assert_eq!(parts, vec![("hi ", false), ("@sam", true), (" and ", false), ("@al", true), ("!", false)]);
+    }
+
+    #[test]
+    fn parts_without_mentions_is_the_whole_message() {
+        let parts = parts("plain", &[]);
+        assert_eq!(parts.len(), 1);
+        assert!(!parts[0].mention);
+        assert!(super::parts("", &[]).is_empty());
+    }
+}
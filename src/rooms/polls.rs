@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+const MIN_OPTIONS: usize = 2;
+const MAX_OPTIONS: usize = 10;
+const MAX_QUESTION_LENGTH: usize = 200;
+const MAX_OPTION_LENGTH: usize = 100;
+// starting another one drops the oldest closed poll
+const MAX_POLLS: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct Poll {
+    pub id: u64,
+    pub question: String,
+    pub options: Vec<String>,
+    pub author: String,
+    // connection that started it, they can close it along with the owner
+    author_id: String,
+    // impermachat_id to option index, voting again moves your vote
+    votes: HashMap<String, usize>,
+    pub closed: bool,
+}
+
+impl Poll {
+    fn counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.options.len()];
+        for option in self.votes.values() {
+            counts[*option] += 1;
+        }
+        counts
+    }
+
+    /// One line summary for the chat log, like "Pizza 3, Tacos 1".
+    pub fn results(&self) -> String {
+        self.options.iter()
+            .zip(self.counts())
+            .map(|(option, count)| format!("{} {}", option, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[derive(Clone)]
+pub struct PollOptionView {
+    // 1-based, the same numbers /vote takes
+    pub number: usize,
+    pub label: String,
+    pub votes: usize,
+    pub percent: usize,
+    pub chosen: bool,
+}
+
+#[derive(Clone)]
+pub struct PollView {
+    pub id: u64,
+    pub question: String,
+    pub author: String,
+    pub closed: bool,
+    pub total: usize,
+    pub options: Vec<PollOptionView>,
+    pub can_vote: bool,
+    pub can_close: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Polls {
+    polls: Vec<Poll>,
+    next_id: u64,
+    // closed by close_all rather than by anyone, so extending the room can open them again
+    closed_by_expiry: Vec<u64>,
+}
+
+impl Polls {
+    pub fn create(&mut self, author: &str, author_id: &str, question: &str, options: &[&str]) -> Result<&Poll, &'static str> {
+        let question = question.trim();
+        let options: Vec<String> = options.iter()
+            .map(|option| option.trim())
+            .filter(|option| !option.is_empty())
+            .map(str::to_string)
+            .collect();
+        if question.is_empty() {
+            return Err("A poll needs a question");
+        }
+        if question.chars().count() > MAX_QUESTION_LENGTH {
+            return Err("Keep the question under 200 characters");
+        }
+        if options.len() < MIN_OPTIONS || options.len() > MAX_OPTIONS {
+            return Err("A poll needs between 2 and 10 options");
+        }
+        if options.iter().any(|option| option.chars().count() > MAX_OPTION_LENGTH) {
+            return Err("Keep each option under 100 characters");
+        }
+        if self.polls.len() >= MAX_POLLS {
+            let Some(oldest_closed) = self.polls.iter().position(|poll| poll.closed) else {
+                return Err("Close one of the open polls before starting another");
+            };
+            self.polls.remove(oldest_closed);
+        }
+
+        self.next_id += 1;
+        self.polls.push(Poll {
+            id: self.next_id,
+            question: question.to_string(),
+            options,
+            author: author.to_string(),
+            author_id: author_id.to_string(),
+            votes: HashMap::new(),
+            closed: false,
+        });
+        Ok(self.polls.last().expect("Just pushed"))
+    }
+
+    /// Records a vote for the 1-based `option`.
+    pub fn vote(&mut self, poll_id: u64, connection_id: &str, option: usize) -> Result<(), &'static str> {
+        let poll = self.polls.iter_mut()
+            .find(|poll| poll.id == poll_id)
+            .ok_or("That poll is gone")?;
+        if poll.closed {
+            return Err("That poll is closed");
+        }
+        if option == 0 || option > poll.options.len() {
+            return Err("That poll doesn't have that option");
+        }
+        poll.votes.insert(connection_id.to_string(), option - 1);
+        Ok(())
+    }
+
+    /// The newest poll still taking votes, what /vote applies to.
+    pub fn latest_open(&self) -> Option<u64> {
+        self.polls.iter().rev().find(|poll| !poll.closed).map(|poll| poll.id)
+    }
+
+    pub fn close(&mut self, poll_id: u64, connection_id: &str, is_owner: bool) -> Result<&Poll, &'static str> {
+        let poll = self.polls.iter_mut()
+            .find(|poll| poll.id == poll_id)
+            .ok_or("That poll is gone")?;
+        if !is_owner && poll.author_id != connection_id {
+            return Err("Only whoever started the poll or the room's owner can close it");
+        }
+        if poll.closed {
+            return Err("That poll is already closed");
+        }
+        poll.closed = true;
+        Ok(poll)
+    }
+
+    /// Closes every open poll, returning them so their results can be announced.
+    pub fn close_all(&mut self) -> Vec<Poll> {
+        let closed: Vec<Poll> = self.polls.iter_mut()
+            .filter(|poll| !poll.closed)
+            .map(|poll| {
+                poll.closed = true;
+                poll.clone()
+            })
+            .collect();
+        self.closed_by_expiry.extend(closed.iter().map(|poll| poll.id));
+        closed
+    }
+
+    /// Opens the polls close_all shut again, returning the ones still around.
+    pub fn reopen_all(&mut self) -> Vec<Poll> {
+        let ids = std::mem::take(&mut self.closed_by_expiry);
+        self.polls.iter_mut()
+            .filter(|poll| poll.closed && ids.contains(&poll.id))
+            .map(|poll| {
+                poll.closed = false;
+                poll.clone()
+            })
+            .collect()
+    }
+
+    pub fn views(&self, connection_id: &str, is_owner: bool, can_vote: bool) -> Vec<PollView> {
+        self.polls.iter()
+            .map(|poll| {
+                let counts = poll.counts();
+                let total = poll.votes.len();
+                let chosen = poll.votes.get(connection_id).copied();
+                PollView {
+                    id: poll.id,
+                    question: poll.question.clone(),
+                    author: poll.author.clone(),
+                    closed: poll.closed,
+                    total,
+                    options: poll.options.iter()
+                        .zip(counts)
+                        .enumerate()
+                        .map(|(index, (label, votes))| PollOptionView {
+                            number: index + 1,
+                            label: label.clone(),
+                            votes,
+                            percent: (votes * 100).checked_div(total).unwrap_or(0),
+                            chosen: chosen == Some(index),
+                        })
+                        .collect(),
+                    can_vote: can_vote && !poll.closed,
+                    can_close: !poll.closed && (is_owner || poll.author_id == connection_id),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lunch(polls: &mut Polls) -> u64 {
+        polls.create("sam", "sam-id", "Lunch?", &["Pizza", " Tacos ", ""]).unwrap().id
+    }
+
+    #[test]
+    fn trims_and_validates_new_polls() {
+        let mut polls = Polls::default();
+        let poll = polls.create("sam", "sam-id", "  Lunch?  ", &[" Pizza ", "", "Tacos"]).unwrap();
+        assert_eq!(poll.question, "Lunch?");
+        assert_eq!(poll.options, vec!["Pizza", "Tacos"]);
+
+        assert!(polls.create("sam", "sam-id", "  ", &["a", "b"]).is_err());
+        assert!(polls.create("sam", "sam-id", "One?", &["a", " "]).is_err());
+        assert!(polls.create("sam", "sam-id", "Many?", &["x"; MAX_OPTIONS + 1]).is_err());
+        assert!(polls.create("sam", "sam-id", &"?".repeat(MAX_QUESTION_LENGTH + 1), &["a", "b"]).is_err());
+        assert!(polls.create("sam", "sam-id", "Long?", &["a", &"b".repeat(MAX_OPTION_LENGTH + 1)]).is_err());
+    }
+
+    #[test]
+    fn voting_again_moves_the_vote() {
+        let mut polls = Polls::default();
+        let id = lunch(&mut polls);
+        polls.vote(id, "a", 1).unwrap();
+        polls.vote(id, "b", 1).unwrap();
+        polls.vote(id, "a", 2).unwrap();
+
+        let view = &polls.views("a", false, true)[0];
+        assert_eq!(view.total, 2);
+        assert_eq!(view.options.iter().map(|option| option.votes).collect::<Vec<_>>(), vec![1, 1]);
+        assert_eq!(view.options.iter().map(|option| option.percent).collect::<Vec<_>>(), vec![50, 50]);
+        assert!(view.options[1].chosen && !view.options[0].chosen);
+        assert_eq!(polls.polls[0].results(), "Pizza 1, Tacos 1");
+    }
+
+    #[test]
+    fn rejects_votes_for_missing_options_and_closed_polls() {
+        let mut polls = Polls::default();
+        let id = lunch(&mut polls);
+        assert!(polls.vote(id, "a", 0).is_err());
+        assert!(polls.vote(id, "a", 3).is_err());
+        assert!(polls.vote(id + 1, "a", 1).is_err());
+
+        polls.close(id, "sam-id", false).unwrap();
+        assert!(polls.vote(id, "a", 1).is_err());
+        assert_eq!(polls.latest_open(), None);
+    }
+
+    #[test]
+    fn only_the_author_or_owner_can_close() {
+        let mut polls = Polls::default();
+        let first = lunch(&mut polls);
+        let second = lunch(&mut polls);
+        assert!(polls.close(first, "someone-else", false).is_err());
+        assert!(!polls.views("someone-else", false, true)[0].can_close);
+        assert!(polls.views("someone-else", true, true)[0].can_close);
+
+        polls.close(first, "someone-else", true).unwrap();
+        assert!(polls.close(first, "sam-id", false).is_err());
+        assert_eq!(polls.latest_open(), Some(second));
+    }
+
+    #[test]
+    fn empty_polls_show_zero_percent() {
+        let mut polls = Polls::default();
+        lunch(&mut polls);
+        let view = &polls.views("a", false, false)[0];
+        assert!(view.options.iter().all(|option| option.percent == 0));
+        assert!(!view.can_vote);
+    }
+
+    #[test]
+    fn a_full_board_drops_the_oldest_closed_poll() {
+        let mut polls = Polls::default();
+        let ids: Vec<u64> = (0..MAX_POLLS).map(|_| lunch(&mut polls)).collect();
+        assert!(polls.create("sam", "sam-id", "Sixth?", &["a", "b"]).is_err());
+
+        polls.close(ids[2], "sam-id", false).unwrap();
+        polls.close(ids[1], "sam-id", false).unwrap();
+        polls.create("sam", "sam-id", "Sixth?", &["a", "b"]).unwrap();
+        let remaining: Vec<u64> = polls.polls.iter().map(|poll| poll.id).collect();
+        assert_eq!(remaining.len(), MAX_POLLS);
+        assert!(!remaining.contains(&ids[1]));
+        assert!(remaining.contains(&ids[2]));
+    }
+
+    #[test]
+    fn reopen_all_skips_polls_closed_by_hand() {
+        let mut polls = Polls::default();
+        let first = lunch(&mut polls);
+        let second = lunch(&mut polls);
+        polls.close(first, "sam-id", false).unwrap();
+        polls.close_all();
+
+        let reopened = polls.reopen_all();
+        assert_eq!(reopened.iter().map(|poll| poll.id).collect::<Vec<_>>(), vec![second]);
+        assert_eq!(polls.latest_open(), Some(second));
+        assert!(polls.reopen_all().is_empty());
+    }
+
+    #[test]
+    fn close_all_returns_only_the_open_ones() {
+        let mut polls = Polls::default();
+        let first = lunch(&mut polls);
+        lunch(&mut polls);
+        polls.close(first, "sam-id", false).unwrap();
+
+        let closed = polls.close_all();
+        assert_eq!(closed.len(), 1);
+        assert_ne!(closed[0].id, first);
+        assert!(polls.close_all().is_empty());
+    }
+}
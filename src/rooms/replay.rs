@@ -0,0 +1,92 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+// keeps memory bounded for long drafts, older frames get thinned out past this
+const MAX_FRAMES: usize = 600;
+// room wide, once sent recordings go past this the oldest ones are dropped
+const MAX_RECORDED_BYTES: usize = 8 * 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub offset: Duration,
+    pub content: String,
+}
+
+#[derive(Clone, Debug)]
+struct Recording {
+    started: Instant,
+    snapshots: Vec<Snapshot>,
+}
+
+/// Timestamped typing snapshots, collected while someone drafts and kept per message once sent.
+#[derive(Clone, Debug, Default)]
+pub struct TypingRecorder {
+    drafts: HashMap<String, Recording>,
+    // ordered by message id so the oldest recordings go first
+    replays: BTreeMap<u64, Vec<Snapshot>>,
+    recorded_bytes: usize,
+}
+
+fn size(snapshots: &[Snapshot]) -> usize {
+    snapshots.iter().map(|snapshot| snapshot.content.len()).sum()
+}
+
+impl TypingRecorder {
+    pub fn record(&mut self, name: &str, content: &str) {
+        let recording = self.drafts.entry(name.to_string())
+            .or_insert_with(|| Recording {
+                started: Instant::now(),
+                snapshots: Vec::new(),
+            });
+
+        if recording.snapshots.len() >= MAX_FRAMES {
+            // drop every other frame, the replay stays the same length just choppier
+            let mut index = 0;
+            recording.snapshots.retain(|_| {
+                index += 1;
+                index % 2 == 0
+            });
+        }
+
+        recording.snapshots.push(Snapshot {
+            offset: recording.started.elapsed(),
+            content: content.to_string(),
+        });
+    }
+
+    /// Moves the draft's recording onto the sent message, returns whether there was anything to keep
+    /// and the ids of older messages whose recordings were dropped to make room.
+    pub fn finish(&mut self, name: &str, message_id: u64) -> (bool, Vec<u64>) {
+        let Some(recording) = self.drafts.remove(name).filter(|recording| !recording.snapshots.is_empty()) else {
+            return (false, Vec::new());
+        };
+        self.recorded_bytes += size(&recording.snapshots);
+        self.replays.insert(message_id, recording.snapshots);
+
+        let mut evicted = Vec::new();
+        while self.recorded_bytes > MAX_RECORDED_BYTES {
+            let Some((id, snapshots)) = self.replays.pop_first() else {
+                break;
+            };
+            self.recorded_bytes -= size(&snapshots);
+            evicted.push(id);
+        }
+        (!evicted.contains(&message_id), evicted)
+    }
+
+    pub fn discard(&mut self, name: &str) {
+        self.drafts.remove(name);
+    }
+
+    pub fn replay(&self, message_id: u64) -> Option<Vec<Snapshot>> {
+        self.replays.get(&message_id).cloned()
+    }
+}
@@ -1,9 +1,11 @@
+use std::sync::Arc;
 use http::HeaderValue;
 use axum::{
     Router,
     routing::{
         get,
         post,
+        delete,
     },
     middleware,
 };
@@ -14,18 +16,40 @@ use super::handlers::{
     connect_to_room,
     submit_message,
     update_room,
+    set_typing_visibility,
+    search_room,
+    export_room,
+    watch_export_room,
+    watch_search_room,
     render_room,
     set_name,
+    render_spectator_room,
+    watch_room,
+    replay_message,
+    watch_replay_message,
+    extend_room,
+    set_room_topic,
+    pin_message,
+    unpin_message,
+    set_alerts,
+    create_poll,
+    vote_in_poll,
+    close_poll,
+    post_message,
+    list_invites,
+    create_invite,
+    revoke_invite,
     AllRooms,
 };
 
 use super::middleware::ensure_uid;
 
-pub fn rooms_router() -> Router<()> {
-    let rooms = AllRooms::new();
-
+pub fn rooms_router(rooms: Arc<AllRooms>) -> Router<()> {
     let sse_router = Router::new()
-        .route("/connect", get(connect_to_room))
+        .route("/room/:room_id/connect", get(connect_to_room))
+        .route("/watch/:spectator_key/connect", get(watch_room))
+        .route("/room/:room_id/replay/:message_id", get(replay_message))
+        .route("/watch/:spectator_key/replay/:message_id", get(watch_replay_message))
         .layer(SetResponseHeaderLayer::overriding(
             http::header::CONTENT_TYPE,
             HeaderValue::from_static("text/event-stream"),
@@ -41,10 +65,26 @@ pub fn rooms_router() -> Router<()> {
 
     Router::new()
         .route("/room/:room_id", get(render_room))
-        .nest("/room/:room_id", sse_router)
+        .route("/watch/:spectator_key", get(render_spectator_room))
+        .merge(sse_router)
         .route("/room/:room_id/live", post(update_room))
         .route("/room/:room_id/submit", post(submit_message))
+        .route("/room/:room_id/messages", post(post_message))
+        .route("/room/:room_id/typing-visibility", post(set_typing_visibility))
+        .route("/room/:room_id/search", post(search_room))
+        .route("/watch/:spectator_key/search", post(watch_search_room))
+        .route("/room/:room_id/export/:format", get(export_room))
+        .route("/watch/:spectator_key/export/:format", get(watch_export_room))
         .route("/room/:room_id/name", post(set_name))
+        .route("/room/:room_id/extend", post(extend_room))
+        .route("/room/:room_id/topic", post(set_room_topic))
+        .route("/room/:room_id/pins/:message_id", post(pin_message).delete(unpin_message))
+        .route("/alerts", post(set_alerts))
+        .route("/room/:room_id/polls", post(create_poll))
+        .route("/room/:room_id/polls/:poll_id/vote/:option", post(vote_in_poll))
+        .route("/room/:room_id/polls/:poll_id/close", post(close_poll))
+        .route("/room/:room_id/invites", get(list_invites).post(create_invite))
+        .route("/room/:room_id/invites/:token", delete(revoke_invite))
         .layer(ServiceBuilder::new().layer(middleware::from_fn(ensure_uid)))
         .with_state(rooms)
 }
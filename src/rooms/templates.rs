@@ -1,12 +1,31 @@
 use std::collections::HashMap;
 use askama::Template;
 
-use super::handlers::Message;
+use super::handlers::{
+    AlertPrefs,
+    Message,
+};
+use super::invites::InviteView;
+use super::polls::PollView;
 
 #[derive(Template)]
 #[template(path="room.html")]
 pub struct RoomTemplate {
     pub room_id: String,
+    pub title: String,
+    pub spectator_key: String,
+    pub spectator: bool,
+    pub is_owner: bool,
+    // only an encrypted room nobody has written in yet can get a fresh key
+    pub can_create_key: bool,
+    pub invite: String,
+    pub encrypted: bool,
+    pub can_export: bool,
+    pub topic: Option<String>,
+    pub invite_only: bool,
+    // empty unless the owner is looking at an unencrypted room
+    pub post_token: String,
+    pub alerts: AlertPrefs,
 }
 
 // #[derive(Template)]
@@ -16,15 +35,22 @@ pub struct RoomTemplate {
 //     person: String,
 // }
 
+#[derive(Template)]
+#[template(path = "room_not_found.html")]
+pub struct RoomNotFoundTemplate {}
+
 #[derive(Template)]
 #[template(path = "shutdown_room.html")]
-pub struct ShutdownTemplate {}
+pub struct ShutdownTemplate {
+    pub reason: Option<&'static str>,
+}
 
 #[derive(Template)]
 #[template(path = "submit_message.html")]
 pub struct SubmitTemplate {
     pub messages: Vec<Message>,
     pub connection_id: String,
+    pub can_pin: bool,
 }
 
 #[derive(Template)]
@@ -34,6 +60,13 @@ pub struct TypingTemplate {
     pub connection_id: String,
 }
 
+#[derive(Template)]
+#[template(path = "typing_box.html")]
+pub struct TypingBoxTemplate {
+    pub person: String,
+    pub message: Message,
+}
+
 #[derive(Template)]
 #[template(path = "init_name.html")]
 pub struct InitNameTemplate {
@@ -49,6 +82,9 @@ pub struct MajorErrorTemplate {}
 pub struct ChatInputTemplate {
     pub room_id: String,
     pub person: String,
+    pub encrypted: bool,
+    pub room_hides_typing: bool,
+    pub hide_typing: bool,
 }
 
 #[derive(Template)]
@@ -64,3 +100,76 @@ pub struct SetNameTemplate {
 //     target_id: String,
 //     message: String,
 // }
+
+#[derive(Template)]
+#[template(path = "waiting_line.html")]
+pub struct WaitingTemplate {
+    pub position: usize,
+    pub person: String,
+}
+
+#[derive(Template)]
+#[template(path = "spectator.html")]
+pub struct SpectatorTemplate {}
+
+#[derive(Template)]
+#[template(path = "invite_required.html")]
+pub struct InviteRequiredTemplate {}
+
+#[derive(Template)]
+#[template(path = "invite_list.html")]
+pub struct InviteListTemplate {
+    pub room_id: String,
+    pub invite_only: bool,
+    pub invites: Vec<InviteView>,
+}
+
+#[derive(Template)]
+#[template(path = "replay.html")]
+pub struct ReplayTemplate {
+    pub message: Message,
+    pub done: bool,
+}
+
+#[derive(Template)]
+#[template(path = "search_results.html")]
+pub struct SearchResultsTemplate {
+    pub searched: bool,
+    pub encrypted: bool,
+    pub total: usize,
+    pub results: Vec<Message>,
+}
+
+#[derive(Template)]
+#[template(path = "expiry_warning.html")]
+pub struct ExpiryWarningTemplate {
+    pub room_id: String,
+    pub remaining: String,
+    pub final_countdown: bool,
+    pub is_owner: bool,
+}
+
+#[derive(Template)]
+#[template(path = "notice.html")]
+pub struct NoticeTemplate {
+    pub lines: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "topic.html")]
+pub struct TopicTemplate {
+    pub topic: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "polls.html")]
+pub struct PollsTemplate {
+    pub polls: Vec<PollView>,
+}
+
+#[derive(Template)]
+#[template(path = "pinned.html")]
+pub struct PinnedTemplate {
+    pub messages: Vec<Message>,
+    pub can_unpin: bool,
+}
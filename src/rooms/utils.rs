@@ -1,5 +1,16 @@
 use std::time::Duration;
 use axum::http::HeaderMap;
+use hmac::{
+    Hmac,
+    Mac,
+};
+use rand::{
+    distributions::Alphanumeric,
+    Rng,
+};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
 
 pub fn name_to_color(name: &str) -> String {
     let mut hash: u32 = 0;
@@ -25,12 +36,64 @@ pub fn format_time(remaining: Duration) -> String {
 
 }
 
+// coarse wording for banners, the nav timer has the exact count
+pub fn format_remaining(seconds: u64) -> String {
+    match seconds {
+        0..=1 => "a moment".to_string(),
+        2..=59 => format!("{} seconds", seconds),
+        60..=119 => "1 minute".to_string(),
+        _ => format!("{} minutes", seconds.div_ceil(60)),
+    }
+}
+
 pub fn get_connection_cookie(headers: &HeaderMap) -> Option<String> {
+    get_cookie(headers, "impermachat_id")
+}
+
+pub fn get_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
     headers.get("cookie")
         .and_then(|c| c.to_str().ok())
         .and_then(|c| c.split(';')
-            .find(|s| s.trim().starts_with("impermachat_id="))
-            .map(|s| s.trim_start_matches("impermachat_id=").to_string()))
+            .map(|s| s.trim())
+            .find(|s| s.starts_with(&prefix))
+            .map(|s| s.trim_start_matches(&prefix).to_string()))
+}
+
+pub fn random_token(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+pub fn sign(secret: &[u8], message: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn verify_signature(secret: &[u8], message: &str, signature: &str) -> bool {
+    if !signature.len().is_multiple_of(2) || !signature.is_ascii() {
+        return false;
+    }
+    let Ok(signature_bytes) = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&signature[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>() else {
+        return false;
+    };
+
+    let mut mac = HmacSha256::new_from_slice(secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.verify_slice(&signature_bytes).is_ok()
 }
 
 pub fn create_fragments_event(rendered_template: String) -> String {
@@ -40,3 +103,41 @@ pub fn create_fragments_event(rendered_template: String) -> String {
     }
     raw_event
 }
+
+// full SSE body for handlers that answer a datastar request directly instead of through the room stream
+pub fn create_fragments_response(rendered_template: &str) -> String {
+    let mut raw_response = String::from("event: datastar-merge-fragments\n");
+    for line in rendered_template.lines() {
+        raw_response.push_str(&format!("data: fragments {}\n", line));
+    }
+    raw_response.push('\n');
+    raw_response
+}
+
+// durations typed into chat, like 90s, 10m, 1h or a bare number of minutes
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim().to_lowercase();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text.as_str(), "m"),
+    };
+    let number: u64 = number.parse().ok().filter(|number| *number > 0)?;
+    let seconds = match unit {
+        "s" | "sec" | "secs" => number,
+        "m" | "min" | "mins" => number.checked_mul(60)?,
+        "h" | "hr" | "hrs" => number.checked_mul(60 * 60)?,
+        _ => return None,
+    };
+    Some(Duration::from_secs(seconds))
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, (seconds % 3600) / 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, 0) => format!("{}m", m),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, 0, _) => format!("{}h", h),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
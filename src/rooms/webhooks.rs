@@ -0,0 +1,329 @@
+use reqwest::{Client, StatusCode, Url};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::time::{sleep, Duration};
+
+use super::utils::sign;
+
+const MAX_ATTEMPTS: u32 = 5;
+#[cfg(not(test))]
+const FIRST_RETRY_AFTER: Duration = Duration::from_secs(1);
+// keeps the retry tests quick
+#[cfg(test)]
+const FIRST_RETRY_AFTER: Duration = Duration::from_millis(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Room activity sent to webhooks, serialized with an `event` field naming the variant.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    RoomCreated { title: String },
+    ParticipantJoined { name: String },
+    // content is left out for encrypted rooms, it would only be ciphertext
+    MessageSubmitted { name: String, content: Option<String> },
+    RoomShutDown { reason: &'static str },
+}
+
+impl WebhookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::RoomCreated { .. } => "room_created",
+            Self::ParticipantJoined { .. } => "participant_joined",
+            Self::MessageSubmitted { .. } => "message_submitted",
+            Self::RoomShutDown { .. } => "room_shut_down",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    room_id: &'a str,
+    // unix seconds, part of the signed body so old deliveries can be told apart
+    timestamp: i64,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+}
+
+/// A URL and the key its deliveries are signed with.
+#[derive(Clone, Debug)]
+pub struct WebhookTarget {
+    pub url: Url,
+    pub secret: String,
+}
+
+/// Server-wide webhook settings, handed to each room as it's created.
+pub struct Webhooks {
+    http: Client,
+    global: Vec<WebhookTarget>,
+}
+
+impl Webhooks {
+    pub fn new(global: Vec<WebhookTarget>) -> Self {
+        Self {
+            http: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("the default TLS backend is available"),
+            global,
+        }
+    }
+
+    /// The targets a room posts to, None when there are none so rooms skip serializing events.
+    pub fn for_room(&self, room_id: &str, room_target: Option<WebhookTarget>) -> Option<RoomWebhooks> {
+        let targets: Vec<WebhookTarget> = self.global.iter().cloned().chain(room_target).collect();
+        if targets.is_empty() {
+            return None;
+        }
+        Some(RoomWebhooks {
+            http: self.http.clone(),
+            room_id: room_id.to_string(),
+            targets,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RoomWebhooks {
+    http: Client,
+    room_id: String,
+    targets: Vec<WebhookTarget>,
+}
+
+impl RoomWebhooks {
+    /// Delivers in the background, a slow receiver never holds up the room.
+    pub fn send(&self, event: WebhookEvent) {
+        let body = match serde_json::to_string(&Payload {
+            room_id: &self.room_id,
+            timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+            event: &event,
+        }) {
+            Ok(body) => body,
+            Err(e) => {
+                println!("Error serializing webhook: {}", e);
+                return;
+            },
+        };
+
+        for target in &self.targets {
+            tokio::spawn(deliver(self.http.clone(), target.clone(), event.name(), body.clone()));
+        }
+    }
+}
+
+// retries network errors, 429s and 5xxs with doubling waits, anything else is final
+async fn deliver(http: Client, target: WebhookTarget, event: &'static str, body: String) {
+    // an unkeyed signature would pass for anyone's, receivers are better off getting nothing
+    if target.secret.is_empty() {
+        println!("Skipping webhook {} to {}, it has no secret to sign with", event, target.url);
+        return;
+    }
+    let signature = format!("sha256={}", sign(target.secret.as_bytes(), &body));
+    let mut wait = FIRST_RETRY_AFTER;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = http.post(target.url.clone())
+            .header("content-type", "application/json")
+            .header("x-impermachat-event", event)
+            .header("x-impermachat-signature", &signature)
+            .body(body.clone())
+            .send()
+            .await;
+
+        let retry = match result {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => {
+                let status = response.status();
+                println!("Webhook {} to {} got {} (attempt {})", event, target.url, status, attempt);
+                status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            },
+            Err(e) => {
+                println!("Webhook {} to {} failed: {} (attempt {})", event, target.url, e, attempt);
+                true
+            },
+        };
+        if !retry {
+            return;
+        }
+        if attempt < MAX_ATTEMPTS {
+            sleep(wait).await;
+            wait *= 2;
+        }
+    }
+    println!("Giving up on webhook {} to {}", event, target.url);
+}
+
+/// Only plain http(s) URLs are accepted as webhook targets.
+pub fn parse_url(url: &str) -> Option<Url> {
+    Url::parse(url.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use axum::{
+        extract::State,
+        http::HeaderMap,
+        routing::post,
+        Router,
+    };
+    use tokio::{
+        net::TcpListener,
+        sync::mpsc,
+        time::timeout,
+    };
+
+    use super::*;
+    use crate::rooms::utils::verify_signature;
+
+    const SECRET: &str = "test-secret";
+
+    struct Receiver {
+        // answered in order, the last one repeats
+        statuses: Vec<StatusCode>,
+        hits: AtomicUsize,
+        requests: mpsc::UnboundedSender<(HeaderMap, String)>,
+    }
+
+    async fn receive(State(receiver): State<Arc<Receiver>>, headers: HeaderMap, body: String) -> StatusCode {
+        let hit = receiver.hits.fetch_add(1, Ordering::SeqCst);
+        receiver.requests.send((headers, body)).expect("test is listening");
+        receiver.statuses[hit.min(receiver.statuses.len() - 1)]
+    }
+
+    // starts a local receiver and returns its URL
+    async fn receiver(statuses: Vec<StatusCode>) -> (Url, mpsc::UnboundedReceiver<(HeaderMap, String)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let receiver = Arc::new(Receiver {
+            statuses,
+            hits: AtomicUsize::new(0),
+            requests: tx,
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = parse_url(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/hook", post(receive)).with_state(receiver)).await.unwrap();
+        });
+        (url, rx)
+    }
+
+    // starts a local receiver and returns a room's webhooks pointed at it
+    async fn listen(statuses: Vec<StatusCode>) -> (RoomWebhooks, mpsc::UnboundedReceiver<(HeaderMap, String)>) {
+        let (url, rx) = receiver(statuses).await;
+        let webhooks = Webhooks::new(vec![WebhookTarget {
+            url,
+            secret: SECRET.to_string(),
+        }]);
+        (webhooks.for_room("standup", None).unwrap(), rx)
+    }
+
+    async fn next(rx: &mut mpsc::UnboundedReceiver<(HeaderMap, String)>) -> Option<(HeaderMap, String)> {
+        timeout(Duration::from_secs(5), rx.recv()).await.ok().flatten()
+    }
+
+    // gives a delivery that shouldn't retry time to do so anyway
+    async fn nothing_more(rx: &mut mpsc::UnboundedReceiver<(HeaderMap, String)>) -> bool {
+        timeout(FIRST_RETRY_AFTER * 20, rx.recv()).await.is_err()
+    }
+
+    #[tokio::test]
+    async fn signs_the_body_and_sends_the_event() {
+        let (webhooks, mut rx) = listen(vec![StatusCode::OK]).await;
+        webhooks.send(WebhookEvent::MessageSubmitted {
+            name: "sam".to_string(),
+            content: Some("hello".to_string()),
+        });
+
+        let (headers, body) = next(&mut rx).await.expect("delivered");
+        let signature = headers["x-impermachat-signature"].to_str().unwrap();
+        let signature = signature.strip_prefix("sha256=").expect("prefixed with the algorithm");
+        assert!(verify_signature(SECRET.as_bytes(), &body, signature));
+        assert!(!verify_signature(b"another-secret", &body, signature));
+        assert_eq!(headers["x-impermachat-event"], "message_submitted");
+        assert_eq!(headers["content-type"], "application/json");
+
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["event"], "message_submitted");
+        assert_eq!(json["room_id"], "standup");
+        assert_eq!(json["name"], "sam");
+        assert_eq!(json["content"], "hello");
+        assert!(json["timestamp"].is_i64());
+        assert_eq!(json.as_object().unwrap().len(), 5);
+        assert!(nothing_more(&mut rx).await);
+    }
+
+    #[tokio::test]
+    async fn leaves_out_encrypted_content() {
+        let (webhooks, mut rx) = listen(vec![StatusCode::OK]).await;
+        webhooks.send(WebhookEvent::MessageSubmitted {
+            name: "sam".to_string(),
+            content: None,
+        });
+
+        let (_, body) = next(&mut rx).await.expect("delivered");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert!(json["content"].is_null());
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits_and_server_errors() {
+        let (webhooks, mut rx) = listen(vec![
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::NO_CONTENT,
+        ]).await;
+        webhooks.send(WebhookEvent::RoomShutDown { reason: "expired" });
+
+        let mut bodies = Vec::new();
+        for _ in 0..3 {
+            bodies.push(next(&mut rx).await.expect("retried").1);
+        }
+        // every attempt carries the same signed body
+        assert!(bodies.iter().all(|body| *body == bodies[0]));
+        assert!(nothing_more(&mut rx).await);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (webhooks, mut rx) = listen(vec![StatusCode::SERVICE_UNAVAILABLE]).await;
+        webhooks.send(WebhookEvent::RoomCreated { title: "standup".to_string() });
+
+        for _ in 0..MAX_ATTEMPTS {
+            next(&mut rx).await.expect("retried");
+        }
+        assert!(nothing_more(&mut rx).await);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (webhooks, mut rx) = listen(vec![StatusCode::BAD_REQUEST, StatusCode::OK]).await;
+        webhooks.send(WebhookEvent::ParticipantJoined { name: "sam".to_string() });
+
+        next(&mut rx).await.expect("delivered");
+        assert!(nothing_more(&mut rx).await);
+    }
+
+    #[tokio::test]
+    async fn skips_targets_without_a_secret() {
+        let (url, mut rx) = receiver(vec![StatusCode::OK]).await;
+        let target = WebhookTarget {
+            url,
+            secret: String::new(),
+        };
+        deliver(Client::new(), target, "room_created", "{}".to_string()).await;
+        assert!(nothing_more(&mut rx).await);
+    }
+
+    #[test]
+    fn only_accepts_http_urls() {
+        assert!(parse_url(" https://example.com/hook ").is_some());
+        assert!(parse_url("http://127.0.0.1:9000").is_some());
+        assert!(parse_url("ftp://example.com").is_none());
+        assert!(parse_url("file:///etc/passwd").is_none());
+        assert!(parse_url("not a url").is_none());
+    }
+}